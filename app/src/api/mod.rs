@@ -0,0 +1,371 @@
+// JSON API for submitting renders outside of Slack (scripts, bookmarklets).
+// Requests are authenticated with "Authorization: Bearer <token>" using the
+// tokens from the config.
+
+use crate::archive::{Archive, Capture};
+use crate::bulk::{self, BulkImports, BulkReport};
+use crate::config::{ApiToken, Config};
+use crate::notify::{ChannelNotifier, EmailNotifier, Notifier, WebhookNotifier};
+use crate::renderer::{new_request_id, RenderOptions, RenderRequest, RenderSender, RenderStatus};
+
+use std::sync::Arc;
+
+use log::{error, info};
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::{Header, Status};
+use rocket::request::{self, FromRequest, Outcome, Request};
+use rocket::response::status::{BadRequest, Custom};
+use rocket::serde::json::Json;
+use rocket::{Response, State};
+use serde::{Deserialize, Serialize};
+
+// Authenticated API client.
+pub struct ApiClient {
+    name: String,
+    admin: bool,
+}
+
+#[derive(Debug)]
+pub enum ApiAuthError {
+    ConfigError,
+    MissingToken,
+    InvalidToken,
+}
+
+fn find_token<'a>(tokens: &'a [ApiToken], token: &str) -> Option<&'a ApiToken> {
+    // Compare against all of them to not leak which one matched via timing.
+    tokens.iter().fold(None, |found, t| {
+        if constant_time_eq::constant_time_eq(t.token.as_bytes(), token.as_bytes()) {
+            Some(t)
+        } else {
+            found
+        }
+    })
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ApiClient {
+    type Error = ApiAuthError;
+
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let config = match req.guard::<&State<Config>>().await {
+            Outcome::Success(config) => config,
+            _ => return Outcome::Error((Status::InternalServerError, ApiAuthError::ConfigError)),
+        };
+
+        let token = match req
+            .headers()
+            .get_one("Authorization")
+            .and_then(|h| h.strip_prefix("Bearer "))
+        {
+            Some(token) => token.trim(),
+            None => return Outcome::Error((Status::Unauthorized, ApiAuthError::MissingToken)),
+        };
+
+        match find_token(&config.api.tokens, token) {
+            Some(t) => Outcome::Success(ApiClient {
+                name: t.name.clone(),
+                admin: t.admin,
+            }),
+            None => {
+                error!("Rejecting invalid API token");
+                Outcome::Error((Status::Unauthorized, ApiAuthError::InvalidToken))
+            }
+        }
+    }
+}
+
+// How long to wait for the render when submitted with ?wait=true.
+const WAIT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(300);
+
+#[derive(Debug, Deserialize)]
+pub struct SubmitRequest {
+    url: String,
+    #[serde(flatten)]
+    options: RenderOptions,
+    // Optional notifications when the render is done.
+    #[serde(default)]
+    webhook: Option<String>,
+    #[serde(default)]
+    email: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StatusResponse {
+    id: String,
+    status_url: String,
+    status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    capture: Option<Capture>,
+}
+
+fn get_status(
+    id: &str,
+    sender: &RenderSender,
+    archive: &Archive,
+    config: &Config,
+) -> Option<StatusResponse> {
+    let (status, error, capture) = match sender.status(id) {
+        Some(RenderStatus::Queued) => ("queued", None, None),
+        Some(RenderStatus::Rendering) => ("rendering", None, None),
+        Some(RenderStatus::Failed(error)) => ("failed", Some(error), None),
+        None => ("done", None, Some(archive.get(id)?)),
+    };
+    Some(StatusResponse {
+        id: id.to_string(),
+        status_url: format!("{}/api/renders/{}", config.hostname, id),
+        status,
+        error,
+        capture,
+    })
+}
+
+#[rocket::post("/renders?<wait>", data = "<request>")]
+async fn submit(
+    client: ApiClient,
+    wait: Option<bool>,
+    request: Json<SubmitRequest>,
+    sender: &State<RenderSender>,
+    archive: &State<Arc<Archive>>,
+    config: &State<Config>,
+) -> Result<Custom<Json<StatusResponse>>, BadRequest<&'static str>> {
+    let request = request.into_inner();
+    let url = url::Url::parse(&request.url).map_err(|_| BadRequest("Invalid URL"))?;
+
+    let mut notifiers: Vec<Box<dyn Notifier>> = vec![];
+    if let Some(webhook) = request.webhook {
+        url::Url::parse(&webhook).map_err(|_| BadRequest("Invalid webhook URL"))?;
+        notifiers.push(Box::new(WebhookNotifier {
+            url: webhook,
+            secret: config.webhooks.secret.clone(),
+        }));
+    }
+    if let Some(email) = request.email {
+        if config.smtp.address.is_none() {
+            return Err(BadRequest("Email notifications are not configured"));
+        }
+        notifiers.push(Box::new(EmailNotifier {
+            smtp: config.smtp.clone(),
+            to: email,
+        }));
+    }
+    let receiver = if wait.unwrap_or(false) {
+        let (notifier, receiver) = ChannelNotifier::new();
+        notifiers.push(Box::new(notifier));
+        Some(receiver)
+    } else {
+        None
+    };
+
+    let id = new_request_id(&url);
+    sender
+        .render(RenderRequest {
+            id: id.clone(),
+            url,
+            options: request.options,
+            notifiers,
+            user: Some(client.name),
+            channel: None,
+            team: None,
+        })
+        .map_err(|_| BadRequest("Internal error"))?;
+
+    // The outcome itself is not needed, the status has all the details.
+    let finished = match receiver {
+        Some(receiver) => rocket::tokio::time::timeout(WAIT_TIMEOUT, receiver)
+            .await
+            .is_ok(),
+        None => false,
+    };
+    let status = get_status(&id, sender, archive, config).ok_or(BadRequest("Internal error"))?;
+    let code = if finished {
+        Status::Ok
+    } else {
+        Status::Accepted
+    };
+    Ok(Custom(code, Json(status)))
+}
+
+#[rocket::get("/renders/<id>")]
+fn status(
+    _client: ApiClient,
+    id: &str,
+    sender: &State<RenderSender>,
+    archive: &State<Arc<Archive>>,
+    config: &State<Config>,
+) -> Option<Json<StatusResponse>> {
+    get_status(id, sender, archive, config).map(Json)
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReloadResponse {
+    domains: usize,
+}
+
+// Reloads domains.yaml, the old configs stay in use if it is invalid. Only
+// for admin tokens, not the ones handed out to scripts.
+#[rocket::post("/config/reload")]
+async fn reload_config(
+    client: ApiClient,
+    config: &State<Config>,
+) -> Result<Json<ReloadResponse>, Custom<String>> {
+    if !client.admin {
+        error!("Rejecting config reload by non-admin {}", client.name);
+        return Err(Custom(
+            Status::Forbidden,
+            "Reloading the config requires an admin token".to_string(),
+        ));
+    }
+    info!("Config reload requested by {}", client.name);
+    let config = config.inner().clone();
+    let result = rocket::tokio::task::spawn_blocking(move || config.reload_domains())
+        .await
+        .map_err(|e| Custom(Status::InternalServerError, e.to_string()))?;
+    match result {
+        Ok(domains) => Ok(Json(ReloadResponse { domains })),
+        Err(e) => {
+            error!(
+                "Failed to reload domain configs, keeping the old ones: {:?}",
+                e
+            );
+            Err(Custom(Status::BadRequest, format!("{:#}", e)))
+        }
+    }
+}
+
+// URLs to import: a list, the text of a URL list, sitemap or feed, or the URL
+// of a sitemap or feed to fetch.
+#[derive(Debug, Deserialize)]
+pub struct ImportRequest {
+    #[serde(default)]
+    urls: Vec<String>,
+    #[serde(default)]
+    content: Option<String>,
+    #[serde(default)]
+    source: Option<String>,
+    // Between renders of the same host.
+    #[serde(default)]
+    delay_seconds: Option<u64>,
+    #[serde(flatten)]
+    options: RenderOptions,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImportResponse {
+    status_url: String,
+    #[serde(flatten)]
+    report: BulkReport,
+}
+
+#[rocket::post("/imports", data = "<request>")]
+async fn start_import(
+    client: ApiClient,
+    request: Json<ImportRequest>,
+    sender: &State<RenderSender>,
+    imports: &State<BulkImports>,
+    config: &State<Config>,
+) -> Result<Custom<Json<ImportResponse>>, Custom<String>> {
+    let request = request.into_inner();
+    let bad_request = |e: anyhow::Error| Custom(Status::BadRequest, format!("{:#}", e));
+    let mut urls = request
+        .urls
+        .iter()
+        .map(|url| url::Url::parse(url).map_err(|_| anyhow::anyhow!("Invalid URL {}", url)))
+        .collect::<anyhow::Result<Vec<_>>>()
+        .map_err(bad_request)?;
+    // Fetching the source and nested sitemaps uses the blocking client.
+    if request.content.is_some() || request.source.is_some() {
+        let (content, source) = (request.content, request.source);
+        let parsed = rocket::tokio::task::spawn_blocking(move || {
+            let mut urls = vec![];
+            if let Some(content) = content {
+                urls.extend(bulk::parse_urls(&content)?);
+            }
+            if let Some(source) = source {
+                urls.extend(bulk::parse_urls(&bulk::fetch_source(&source)?)?);
+            }
+            Ok(urls)
+        })
+        .await
+        .map_err(|e| Custom(Status::InternalServerError, e.to_string()))?;
+        urls.extend(parsed.map_err(bad_request)?);
+    }
+
+    let delay = request
+        .delay_seconds
+        .map_or(bulk::DEFAULT_DELAY, std::time::Duration::from_secs);
+    let id = imports
+        .start(
+            urls,
+            request.options,
+            delay,
+            Some(client.name),
+            config,
+            sender,
+        )
+        .map_err(bad_request)?;
+    let report = imports
+        .get(&id)
+        .ok_or_else(|| Custom(Status::InternalServerError, "Internal error".to_string()))?;
+    Ok(Custom(
+        Status::Accepted,
+        Json(ImportResponse {
+            status_url: format!("{}/api/imports/{}", config.hostname, id),
+            report,
+        }),
+    ))
+}
+
+#[rocket::get("/imports/<id>")]
+fn import_status(
+    _client: ApiClient,
+    id: &str,
+    imports: &State<BulkImports>,
+) -> Option<Json<BulkReport>> {
+    imports.get(id).map(Json)
+}
+
+// CORS preflight, so that bookmarklets can call the API from any page.
+#[rocket::options("/<_..>")]
+fn preflight() {}
+
+pub struct Cors;
+
+#[rocket::async_trait]
+impl Fairing for Cors {
+    fn info(&self) -> Info {
+        Info {
+            name: "API CORS headers",
+            kind: Kind::Response,
+        }
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        if !request.uri().path().starts_with("/api/") {
+            return;
+        }
+        // Authentication is done with tokens, not cookies, so any origin is fine.
+        response.set_header(Header::new("Access-Control-Allow-Origin", "*"));
+        response.set_header(Header::new(
+            "Access-Control-Allow-Methods",
+            "GET, POST, OPTIONS",
+        ));
+        response.set_header(Header::new(
+            "Access-Control-Allow-Headers",
+            "Authorization, Content-Type",
+        ));
+    }
+}
+
+pub fn routes() -> Vec<rocket::Route> {
+    rocket::routes![
+        submit,
+        status,
+        reload_config,
+        start_import,
+        import_status,
+        preflight
+    ]
+}
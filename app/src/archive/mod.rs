@@ -0,0 +1,473 @@
+// Archive of all successful captures with a simple full-text index.
+//
+// Each capture is stored as a JSON file (metadata and extracted text) in the
+// archive directory. On startup all of them are loaded and indexed in memory,
+// which is plenty fast for the few thousand captures a team will make.
+
+use crate::chrome::PageInfo;
+use crate::renderer::RenderResult;
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::RwLock;
+
+use chrono::{DateTime, NaiveDate, Utc};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+// BM25 ranking parameters.
+const BM25_K1: f64 = 1.2;
+const BM25_B: f64 = 0.75;
+
+// Characters of context shown around the match in search results.
+const SNIPPET_LENGTH: usize = 200;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Capture {
+    pub id: String,
+    pub created: DateTime<Utc>,
+    pub title: String,
+    pub orig_url: url::Url,
+    pub pdf_url: Option<String>,
+    pub png_url: Option<String>,
+    #[serde(default)]
+    pub screenshot_tiles: Vec<String>,
+    pub thumbnail_url: Option<String>,
+    pub mhtml_url: Option<String>,
+    pub reader_url: Option<String>,
+    pub markdown_url: Option<String>,
+    pub text_url: Option<String>,
+    pub word_count: Option<usize>,
+    pub page_info: Option<PageInfo>,
+    pub user: Option<String>,
+    pub channel: Option<String>,
+    pub team: Option<String>,
+}
+
+// On-disk format, the text is only needed to build the index.
+#[derive(Serialize, Deserialize)]
+struct StoredCapture {
+    #[serde(flatten)]
+    capture: Capture,
+    text: String,
+}
+
+// Filter for listing captures, all conditions must match.
+#[derive(Debug, Default)]
+pub struct CaptureFilter {
+    // Also matches subdomains.
+    pub domain: Option<String>,
+    pub channel: Option<String>,
+    pub user: Option<String>,
+    // Inclusive date range (UTC).
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+}
+
+impl CaptureFilter {
+    fn matches(&self, capture: &Capture) -> bool {
+        if let Some(ref domain) = self.domain {
+            let host = capture.orig_url.host_str().unwrap_or_default();
+            if host != domain && !host.ends_with(&format!(".{}", domain)) {
+                return false;
+            }
+        }
+        if self.channel.is_some() && capture.channel != self.channel {
+            return false;
+        }
+        if self.user.is_some() && capture.user != self.user {
+            return false;
+        }
+        let date = capture.created.date_naive();
+        if self.from.is_some_and(|from| date < from) || self.to.is_some_and(|to| date > to) {
+            return false;
+        }
+        true
+    }
+}
+
+#[derive(Debug)]
+pub struct SearchHit {
+    pub capture: Capture,
+    pub snippet: String,
+}
+
+struct Document {
+    capture: Capture,
+    text: String,
+    length: usize,
+}
+
+#[derive(Default)]
+struct Index {
+    documents: Vec<Document>,
+    // Term -> (document index, term frequency).
+    postings: HashMap<String, Vec<(usize, u32)>>,
+    total_length: usize,
+}
+
+pub struct Archive {
+    dir: PathBuf,
+    index: RwLock<Index>,
+}
+
+fn tokenize(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|t| !t.is_empty())
+        .map(str::to_lowercase)
+}
+
+// Everything that is searchable: title, url, page metadata and the text itself.
+fn searchable_text(capture: &Capture, text: &str) -> String {
+    let mut fields = vec![capture.title.as_str(), capture.orig_url.as_str()];
+    if let Some(ref info) = capture.page_info {
+        fields.extend(info.description.as_deref());
+        fields.extend(info.author.as_deref());
+    }
+    fields.push(text);
+    fields.join("\n")
+}
+
+fn make_snippet(text: &str, terms: &[String]) -> String {
+    let lowercase = text.to_lowercase();
+    // Lowercasing can change byte offsets for some scripts, only use the
+    // position if it is still a valid char boundary in the original.
+    let position = terms
+        .iter()
+        .filter_map(|term| lowercase.find(term.as_str()))
+        .min()
+        .filter(|p| text.is_char_boundary(*p))
+        .unwrap_or(0);
+    let start = text[..position]
+        .char_indices()
+        .rev()
+        .nth(SNIPPET_LENGTH / 4)
+        .map(|(i, _)| i)
+        .unwrap_or(0);
+    let snippet: String = text[start..].chars().take(SNIPPET_LENGTH).collect();
+    let truncated = text.len() - start > snippet.len();
+    let mut snippet = snippet.split_whitespace().collect::<Vec<_>>().join(" ");
+    if start > 0 {
+        snippet.insert(0, '…');
+    }
+    if truncated {
+        snippet.push('…');
+    }
+    snippet
+}
+
+impl Index {
+    fn add(&mut self, capture: Capture, text: String) {
+        let doc = self.documents.len();
+        let mut frequencies: HashMap<String, u32> = HashMap::new();
+        let mut length = 0;
+        for token in tokenize(&searchable_text(&capture, &text)) {
+            *frequencies.entry(token).or_default() += 1;
+            length += 1;
+        }
+        for (term, frequency) in frequencies {
+            self.postings
+                .entry(term)
+                .or_default()
+                .push((doc, frequency));
+        }
+        self.total_length += length;
+        self.documents.push(Document {
+            capture,
+            text,
+            length,
+        });
+    }
+
+    // All terms must match, the last one can be a prefix (search as you type).
+    fn search(&self, query: &str, limit: usize) -> Vec<SearchHit> {
+        let terms: Vec<String> = tokenize(query).collect();
+        if terms.is_empty() || self.documents.is_empty() {
+            return vec![];
+        }
+        let doc_count = self.documents.len() as f64;
+        let average_length = self.total_length as f64 / doc_count;
+
+        let mut scores: HashMap<usize, (usize, f64)> = HashMap::new();
+        for (i, term) in terms.iter().enumerate() {
+            let postings: Vec<&Vec<(usize, u32)>> = if i + 1 == terms.len() {
+                self.postings
+                    .iter()
+                    .filter(|(t, _)| t.starts_with(term.as_str()))
+                    .map(|(_, p)| p)
+                    .collect()
+            } else {
+                self.postings.get(term).into_iter().collect()
+            };
+            let mut matched = std::collections::HashSet::new();
+            for posting in postings {
+                let idf = (1.0
+                    + (doc_count - posting.len() as f64 + 0.5) / (posting.len() as f64 + 0.5))
+                    .ln();
+                for &(doc, frequency) in posting {
+                    let frequency = frequency as f64;
+                    let length = self.documents[doc].length as f64;
+                    let score = idf * frequency * (BM25_K1 + 1.0)
+                        / (frequency + BM25_K1 * (1.0 - BM25_B + BM25_B * length / average_length));
+                    let entry = scores.entry(doc).or_default();
+                    if matched.insert(doc) {
+                        entry.0 += 1;
+                    }
+                    entry.1 += score;
+                }
+            }
+        }
+
+        let mut hits: Vec<_> = scores
+            .into_iter()
+            .filter(|(_, (matched_terms, _))| *matched_terms == terms.len())
+            .map(|(doc, (_, score))| (doc, score))
+            .collect();
+        hits.sort_by(|a, b| b.1.total_cmp(&a.1));
+        hits.into_iter()
+            .take(limit)
+            .map(|(doc, _)| {
+                let document = &self.documents[doc];
+                SearchHit {
+                    capture: document.capture.clone(),
+                    snippet: make_snippet(&document.text, &terms),
+                }
+            })
+            .collect()
+    }
+}
+
+impl Archive {
+    pub fn open(dir: PathBuf) -> anyhow::Result<Archive> {
+        std::fs::create_dir_all(&dir)?;
+        let mut stored = Vec::new();
+        for entry in std::fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                continue;
+            }
+            let capture = std::fs::read_to_string(&path)
+                .map_err(anyhow::Error::from)
+                .and_then(|data| Ok(serde_json::from_str::<StoredCapture>(&data)?));
+            match capture {
+                Ok(capture) => stored.push(capture),
+                Err(e) => warn!("Skipping invalid capture {:?}: {:?}", path, e),
+            }
+        }
+        stored.sort_by_key(|s| s.capture.created);
+
+        let mut index = Index::default();
+        for s in stored {
+            index.add(s.capture, s.text);
+        }
+        info!("Loaded {} captures from archive", index.documents.len());
+
+        Ok(Archive {
+            dir,
+            index: RwLock::new(index),
+        })
+    }
+
+    // Stores and indexes a successful render.
+    pub fn add(&self, result: &RenderResult) -> anyhow::Result<Capture> {
+        let stored = StoredCapture {
+            capture: Capture {
+                id: result.id.clone(),
+                created: Utc::now(),
+                title: result.title.clone(),
+                orig_url: result.orig_url.clone(),
+                pdf_url: result.pdf_url.clone(),
+                png_url: result.png_url.clone(),
+                screenshot_tiles: result.screenshot_tiles.clone(),
+                thumbnail_url: result.thumbnail_url.clone(),
+                mhtml_url: result.mhtml_url.clone(),
+                reader_url: result.reader_url.clone(),
+                markdown_url: result.markdown_url.clone(),
+                text_url: result.text_url.clone(),
+                word_count: result.word_count,
+                page_info: result.page_info.clone(),
+                user: result.user.clone(),
+                channel: result.channel.clone(),
+                team: result.team.clone(),
+            },
+            text: result.text.clone().unwrap_or_default(),
+        };
+        let path = self.dir.join(format!("{}.json", stored.capture.id));
+        std::fs::write(path, serde_json::to_string(&stored)?)?;
+
+        let capture = stored.capture.clone();
+        self.index.write().unwrap().add(stored.capture, stored.text);
+        Ok(capture)
+    }
+
+    pub fn search(&self, query: &str, limit: usize) -> Vec<SearchHit> {
+        self.index.read().unwrap().search(query, limit)
+    }
+
+    pub fn get(&self, id: &str) -> Option<Capture> {
+        self.index
+            .read()
+            .unwrap()
+            .documents
+            .iter()
+            .find(|d| d.capture.id == id)
+            .map(|d| d.capture.clone())
+    }
+
+    // Returns the requested page of matching captures (newest first) and the total count.
+    pub fn list(
+        &self,
+        filter: &CaptureFilter,
+        offset: usize,
+        limit: usize,
+    ) -> (Vec<Capture>, usize) {
+        let index = self.index.read().unwrap();
+        let matching: Vec<_> = index
+            .documents
+            .iter()
+            .rev()
+            .filter(|d| filter.matches(&d.capture))
+            .collect();
+        let page = matching
+            .iter()
+            .skip(offset)
+            .take(limit)
+            .map(|d| d.capture.clone())
+            .collect();
+        (page, matching.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn capture(id: &str, url: &str, created: &str) -> Capture {
+        Capture {
+            id: id.to_string(),
+            created: created.parse().unwrap(),
+            title: id.to_string(),
+            orig_url: url::Url::parse(url).unwrap(),
+            pdf_url: None,
+            png_url: None,
+            screenshot_tiles: vec![],
+            thumbnail_url: None,
+            mhtml_url: None,
+            reader_url: None,
+            markdown_url: None,
+            text_url: None,
+            word_count: None,
+            page_info: None,
+            user: None,
+            channel: None,
+            team: None,
+        }
+    }
+
+    fn search(index: &Index, query: &str) -> Vec<String> {
+        index
+            .search(query, 10)
+            .into_iter()
+            .map(|hit| hit.capture.id)
+            .collect()
+    }
+
+    #[test]
+    fn ranking() {
+        let mut index = Index::default();
+        let texts = [
+            (
+                "once",
+                "The rust compiler and some other words about the weather",
+            ),
+            ("often", "Rust rust rust, everything about rust"),
+            ("long", &format!("rust {}", "filler ".repeat(100))),
+            ("none", "Nothing to see here, only the weather"),
+        ];
+        for (id, text) in texts {
+            let created = "2024-01-01T00:00:00Z";
+            index.add(capture(id, "https://example.com/", created), text.into());
+        }
+        // More occurrences rank higher, long documents lower.
+        assert_eq!(search(&index, "rust"), ["often", "once", "long"]);
+        // All terms must match, the last one as a prefix.
+        assert_eq!(search(&index, "rust weath"), ["once"]);
+        assert_eq!(search(&index, "weath rust"), Vec::<String>::new());
+        assert_eq!(search(&index, "RUST Compiler"), ["once"]);
+        // The title and URL are searchable too.
+        assert_eq!(search(&index, "none"), ["none"]);
+        assert_eq!(search(&index, "example").len(), 4);
+        assert!(search(&index, "?!").is_empty());
+        assert_eq!(index.search("rust", 2).len(), 2);
+    }
+
+    #[test]
+    fn filters() {
+        let mut capture = capture("id", "https://www.example.com/a", "2024-03-10T23:30:00Z");
+        capture.channel = Some("general".to_string());
+        capture.user = Some("alice".to_string());
+        let matches = |filter: CaptureFilter| filter.matches(&capture);
+        let domain = |domain: &str| CaptureFilter {
+            domain: Some(domain.to_string()),
+            ..Default::default()
+        };
+        let date = |date: &str| Some(date.parse().unwrap());
+
+        assert!(matches(CaptureFilter::default()));
+        assert!(matches(domain("example.com")));
+        assert!(matches(domain("www.example.com")));
+        assert!(!matches(domain("ample.com")));
+        assert!(!matches(domain("example.org")));
+        assert!(matches(CaptureFilter {
+            channel: Some("general".to_string()),
+            user: Some("alice".to_string()),
+            ..Default::default()
+        }));
+        assert!(!matches(CaptureFilter {
+            channel: Some("general".to_string()),
+            user: Some("bob".to_string()),
+            ..Default::default()
+        }));
+        // Both ends of the range are inclusive.
+        assert!(matches(CaptureFilter {
+            from: date("2024-03-10"),
+            to: date("2024-03-10"),
+            ..Default::default()
+        }));
+        assert!(!matches(CaptureFilter {
+            from: date("2024-03-11"),
+            ..Default::default()
+        }));
+        assert!(!matches(CaptureFilter {
+            to: date("2024-03-09"),
+            ..Default::default()
+        }));
+    }
+
+    #[test]
+    fn snippets() {
+        let terms = |query: &str| tokenize(query).collect::<Vec<_>>();
+        assert_eq!(
+            make_snippet("Short  text\nwith\tspaces", &terms("text")),
+            "Short text with spaces"
+        );
+
+        let text = format!("{} match {}", "před ".repeat(100), "po ".repeat(100));
+        let snippet = make_snippet(&text, &terms("match"));
+        assert!(snippet.starts_with("…"), "{}", snippet);
+        assert!(snippet.ends_with("…"), "{}", snippet);
+        assert!(snippet.contains("match"), "{}", snippet);
+        assert!(snippet.chars().count() <= SNIPPET_LENGTH + 2);
+
+        // Lowercasing "İ" makes it longer, the match position is off then.
+        let text = format!("{}Žluťoučký kůň", "İ".repeat(10));
+        let snippet = make_snippet(&text, &terms("kůň"));
+        assert!(snippet.ends_with("kůň"), "{}", snippet);
+        let text = format!("{} 日本語のテキスト", "İ".repeat(300));
+        make_snippet(&text, &terms("日本語のテキスト"));
+
+        // Unmatched terms show the beginning.
+        let text = "word ".repeat(100);
+        assert!(make_snippet(&text, &terms("missing")).starts_with("word word"));
+    }
+}
@@ -0,0 +1,320 @@
+// Bulk imports: archiving a whole list of URLs, a sitemap or an RSS/Atom feed.
+// URLs go through the render queue like any other request, but only one URL
+// per domain is queued at a time, with a delay before the next one, so that
+// sites are not hammered.
+
+mod sources;
+pub use sources::{fetch_source, is_http, parse_urls};
+
+use crate::config::Config;
+use crate::notify::Notifier;
+use crate::renderer::{
+    new_request_id, RenderError, RenderOptions, RenderRequest, RenderResult, RenderSender,
+};
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use log::{info, warn};
+use serde::Serialize;
+
+// Larger imports are most likely a mistake (e.g. the wrong sitemap).
+pub const MAX_URLS: usize = 1000;
+pub const DEFAULT_DELAY: Duration = Duration::from_secs(10);
+// Reports of finished imports are kept for this long.
+const REPORT_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(Clone, Debug, Serialize)]
+pub struct BulkItem {
+    pub url: String,
+    // Capture id on success, error message otherwise.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+// Progress and, once done, the summary of an import.
+#[derive(Clone, Debug, Serialize)]
+pub struct BulkReport {
+    pub id: String,
+    pub done: bool,
+    pub total: usize,
+    pub pending: usize,
+    pub succeeded: Vec<BulkItem>,
+    pub failed: Vec<BulkItem>,
+    // Not rendered because no domain config matches.
+    pub skipped: Vec<BulkItem>,
+    // Why the import stopped early, the pending URLs were not rendered.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip)]
+    finished: Option<Instant>,
+}
+
+// Reports of running and recently finished imports, by id.
+#[derive(Clone, Default)]
+pub struct BulkImports(Arc<Mutex<HashMap<String, BulkReport>>>);
+
+impl BulkImports {
+    pub fn get(&self, id: &str) -> Option<BulkReport> {
+        self.0.lock().unwrap().get(id).cloned()
+    }
+
+    fn update(&self, id: &str, update: impl FnOnce(&mut BulkReport)) {
+        if let Some(report) = self.0.lock().unwrap().get_mut(id) {
+            update(report);
+        }
+    }
+
+    // Starts importing the URLs in the background, returns the import id.
+    pub fn start(
+        &self,
+        urls: Vec<url::Url>,
+        options: RenderOptions,
+        delay: Duration,
+        user: Option<String>,
+        config: &Config,
+        sender: &RenderSender,
+    ) -> anyhow::Result<String> {
+        anyhow::ensure!(!urls.is_empty(), "No URLs to import");
+        anyhow::ensure!(
+            urls.len() <= MAX_URLS,
+            "Too many URLs ({}), at most {} can be imported at once",
+            urls.len(),
+            MAX_URLS
+        );
+        let id = format!("import-{}", new_request_id(&urls[0]));
+        let mut report = BulkReport {
+            id: id.clone(),
+            done: false,
+            total: urls.len(),
+            pending: 0,
+            succeeded: vec![],
+            failed: vec![],
+            skipped: vec![],
+            error: None,
+            finished: None,
+        };
+        // Queue per host, in the original order.
+        let mut hosts: Vec<(String, VecDeque<url::Url>)> = vec![];
+        for url in urls {
+            if config.find_domain(&url).is_none() {
+                report.skipped.push(BulkItem {
+                    url: url.to_string(),
+                    id: None,
+                    error: Some(RenderError::UnsupportedDomain.to_string()),
+                });
+                continue;
+            }
+            let host = url.host_str().unwrap_or_default().to_string();
+            match hosts.iter_mut().find(|(h, _)| *h == host) {
+                Some((_, queue)) => queue.push_back(url),
+                None => hosts.push((host, VecDeque::from([url]))),
+            }
+        }
+        report.pending = report.total - report.skipped.len();
+        info!(
+            "Starting import {} of {} URLs from {} hosts",
+            id,
+            report.total,
+            hosts.len()
+        );
+        let mut reports = self.0.lock().unwrap();
+        reports.retain(|_, r| r.finished.is_none_or(|f| f.elapsed() < REPORT_TTL));
+        reports.insert(id.clone(), report);
+        drop(reports);
+
+        let job = BulkJob {
+            id: id.clone(),
+            imports: self.clone(),
+            sender: sender.clone(),
+            options,
+            delay,
+            user,
+        };
+        std::thread::spawn(move || job.run(hosts));
+        Ok(id)
+    }
+}
+
+struct BulkJob {
+    id: String,
+    imports: BulkImports,
+    sender: RenderSender,
+    options: RenderOptions,
+    delay: Duration,
+    user: Option<String>,
+}
+
+type Outcome = (usize, String, Result<String, String>);
+
+// Reports finished renders back to the import job.
+#[derive(Debug)]
+struct BulkNotifier {
+    host: usize,
+    url: String,
+    sender: Mutex<mpsc::Sender<Outcome>>,
+    reported: AtomicBool,
+}
+
+impl BulkNotifier {
+    fn send(&self, result: Result<String, String>) -> anyhow::Result<()> {
+        self.reported.store(true, Ordering::Relaxed);
+        let outcome = (self.host, self.url.clone(), result);
+        Ok(self.sender.lock().unwrap().send(outcome)?)
+    }
+}
+
+// Requests dropped without a result (e.g. the render thread panicked) would
+// otherwise keep the import waiting forever.
+impl Drop for BulkNotifier {
+    fn drop(&mut self) {
+        if !self.reported.load(Ordering::Relaxed) {
+            let _ = self.send(Err("Render was dropped without a result".to_string()));
+        }
+    }
+}
+
+impl Notifier for BulkNotifier {
+    fn on_success(&self, result: &RenderResult) -> anyhow::Result<()> {
+        self.send(Ok(result.id.clone()))
+    }
+
+    fn on_failure(&self, _request: &RenderRequest, error: &RenderError) -> anyhow::Result<()> {
+        self.send(Err(error.to_string()))
+    }
+}
+
+impl BulkJob {
+    // Always marks the report as done, so that clients stop waiting.
+    fn run(self, hosts: Vec<(String, VecDeque<url::Url>)>) {
+        let result = self.render_all(hosts);
+        self.imports.update(&self.id, |report| {
+            report.done = true;
+            report.finished = Some(Instant::now());
+            if let Err(e) = result {
+                warn!("Import {} stopped: {:#}", report.id, e);
+                report.error = Some(format!("{:#}", e));
+            }
+            info!(
+                "Import {} done: {} succeeded, {} failed, {} skipped",
+                report.id,
+                report.succeeded.len(),
+                report.failed.len(),
+                report.skipped.len()
+            );
+        });
+    }
+
+    fn render_all(&self, mut hosts: Vec<(String, VecDeque<url::Url>)>) -> anyhow::Result<()> {
+        let (outcomes, receiver) = mpsc::channel();
+        // Dropped once everything is queued, so that the receiver notices
+        // when all the notifiers are gone.
+        let mut outcomes = Some(outcomes);
+        // Per host: whether a render is queued, and when the next one can be.
+        let mut busy = vec![false; hosts.len()];
+        let mut ready_at = vec![Instant::now(); hosts.len()];
+        let mut pending: usize = hosts.iter().map(|(_, queue)| queue.len()).sum();
+
+        while pending > 0 {
+            let now = Instant::now();
+            for (host, (_, queue)) in hosts.iter_mut().enumerate() {
+                if busy[host] || ready_at[host] > now {
+                    continue;
+                }
+                let url = match queue.pop_front() {
+                    Some(url) => url,
+                    None => continue,
+                };
+                let sender = outcomes.clone().expect("Sender dropped with URLs left");
+                let notifier = BulkNotifier {
+                    host,
+                    url: url.to_string(),
+                    sender: Mutex::new(sender),
+                    reported: AtomicBool::new(false),
+                };
+                let request = RenderRequest {
+                    id: new_request_id(&url),
+                    url,
+                    options: self.options.clone(),
+                    notifiers: vec![Box::new(notifier)],
+                    user: self.user.clone(),
+                    channel: None,
+                    team: None,
+                };
+                self.sender
+                    .render(request)
+                    .map_err(|e| anyhow::anyhow!("Render queue is gone: {}", e))?;
+                busy[host] = true;
+            }
+            if hosts.iter().all(|(_, queue)| queue.is_empty()) {
+                outcomes = None;
+            }
+
+            // Wake up for finished renders, or when the next host is ready.
+            let next_ready = (0..hosts.len())
+                .filter(|&host| !busy[host] && !hosts[host].1.is_empty())
+                .map(|host| ready_at[host])
+                .min();
+            let timeout = next_ready.map_or(Duration::from_secs(60), |at| {
+                at.saturating_duration_since(Instant::now())
+            });
+            let (host, url, result) = match receiver.recv_timeout(timeout) {
+                Ok(outcome) => outcome,
+                Err(mpsc::RecvTimeoutError::Timeout) => continue,
+                Err(mpsc::RecvTimeoutError::Disconnected) => {
+                    anyhow::bail!("Renders finished without reporting back")
+                }
+            };
+            busy[host] = false;
+            ready_at[host] = Instant::now() + self.delay;
+            pending -= 1;
+            self.imports.update(&self.id, |report| {
+                report.pending = pending;
+                match result {
+                    Ok(id) => report.succeeded.push(BulkItem {
+                        url,
+                        id: Some(id),
+                        error: None,
+                    }),
+                    Err(error) => report.failed.push(BulkItem {
+                        url,
+                        id: None,
+                        error: Some(error),
+                    }),
+                }
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dropped_renders_are_reported() {
+        let (sender, receiver) = mpsc::channel();
+        let notifier = |url: &str| BulkNotifier {
+            host: 0,
+            url: url.to_string(),
+            sender: Mutex::new(sender.clone()),
+            reported: AtomicBool::new(false),
+        };
+        let finished = notifier("https://example.com/a");
+        finished.send(Ok("id".to_string())).unwrap();
+        drop(finished);
+        drop(notifier("https://example.com/b"));
+        drop(sender);
+
+        let outcomes: Vec<_> = receiver.iter().collect();
+        assert_eq!(outcomes.len(), 2);
+        assert_eq!(outcomes[0].2, Ok("id".to_string()));
+        assert_eq!(outcomes[1].1, "https://example.com/b");
+        assert!(outcomes[1].2.is_err());
+    }
+}
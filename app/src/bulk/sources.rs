@@ -0,0 +1,180 @@
+// URLs to import from plain text (a list or any text with links), sitemaps and RSS/Atom feeds.
+
+use std::io::Read;
+use std::time::Duration;
+
+use log::info;
+
+// Sitemap indexes can list a lot of sitemaps, only this many are fetched.
+const MAX_SITEMAPS: usize = 20;
+// Large sitemaps are up to 50 MB uncompressed, but anything over the URL
+// limit is rejected anyway.
+const MAX_SOURCE_SIZE: u64 = 10 * 1024 * 1024;
+const FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub fn is_http(source: &str) -> bool {
+    source.starts_with("http://") || source.starts_with("https://")
+}
+
+// Downloads a sitemap or feed. Only http(s), the server must never read local
+// files named by (untrusted) sources.
+pub fn fetch_source(source: &str) -> anyhow::Result<String> {
+    anyhow::ensure!(is_http(source), "{} is not an http(s) URL", source);
+    let response = reqwest::blocking::Client::builder()
+        .timeout(FETCH_TIMEOUT)
+        .build()?
+        .get(source)
+        .send()?
+        .error_for_status()?;
+    let mut content = String::new();
+    response
+        .take(MAX_SOURCE_SIZE + 1)
+        .read_to_string(&mut content)?;
+    anyhow::ensure!(
+        content.len() as u64 <= MAX_SOURCE_SIZE,
+        "{} is larger than {} bytes",
+        source,
+        MAX_SOURCE_SIZE
+    );
+    Ok(content)
+}
+
+// Extracts the URLs in order, without duplicates. Nested sitemaps of a sitemap index are fetched.
+pub fn parse_urls(content: &str) -> anyhow::Result<Vec<url::Url>> {
+    let content = content.trim_start_matches('\u{feff}').trim_start();
+    let urls = if !content.starts_with('<') {
+        text_urls(content)
+    } else if content.contains("<sitemapindex") {
+        let mut urls = vec![];
+        for sitemap in tag_texts(content, "loc").into_iter().take(MAX_SITEMAPS) {
+            info!("Fetching sitemap {}", sitemap);
+            urls.extend(tag_texts(&fetch_source(&sitemap)?, "loc"));
+        }
+        urls
+    } else if content.contains("<urlset") {
+        tag_texts(content, "loc")
+    } else if content.contains("<rss") || content.contains("<rdf:RDF") {
+        // Only links of the items, the channel link is the site itself.
+        blocks(content, "item")
+            .into_iter()
+            .flat_map(|item| tag_texts(item, "link"))
+            .collect()
+    } else if content.contains("<feed") {
+        blocks(content, "entry")
+            .into_iter()
+            .filter_map(atom_link)
+            .collect()
+    } else {
+        anyhow::bail!("Unsupported XML, expected a sitemap, RSS or Atom feed");
+    };
+
+    let mut seen = std::collections::HashSet::new();
+    Ok(urls
+        .iter()
+        .filter_map(|url| url::Url::parse(url).ok())
+        .filter(|url| seen.insert(url.clone()))
+        .collect())
+}
+
+fn text_urls(content: &str) -> Vec<String> {
+    let url_regex = regex::Regex::new(r#"https?://[^\s<>"'`]+"#).unwrap();
+    url_regex
+        .find_iter(content)
+        // Punctuation ending a sentence is not part of the URL.
+        .map(|m| {
+            m.as_str()
+                .trim_end_matches(['.', ',', ';', ':', ')', ']', '!', '?'])
+        })
+        .map(str::to_string)
+        .collect()
+}
+
+// Contents of all <tag> elements, from well-formed feeds and sitemaps.
+fn blocks<'a>(content: &'a str, tag: &str) -> Vec<&'a str> {
+    let block_regex = regex::Regex::new(&format!(r"(?s)<{tag}[\s>].*?</{tag}>")).unwrap();
+    block_regex.find_iter(content).map(|m| m.as_str()).collect()
+}
+
+fn tag_texts(content: &str, tag: &str) -> Vec<String> {
+    let text_regex = regex::Regex::new(&format!(
+        r"(?s)<{tag}>\s*(?:<!\[CDATA\[)?(.*?)(?:\]\]>)?\s*</{tag}>"
+    ))
+    .unwrap();
+    text_regex
+        .captures_iter(content)
+        .map(|c| unescape(&c[1]))
+        .collect()
+}
+
+// Atom entries can have several links, the alternate one is the article.
+fn atom_link(entry: &str) -> Option<String> {
+    let link_regex = regex::Regex::new(r"<link\b[^>]*>").unwrap();
+    let href_regex = regex::Regex::new(r#"\bhref\s*=\s*["']([^"']*)["']"#).unwrap();
+    let rel_regex = regex::Regex::new(r#"\brel\s*=\s*["']([^"']*)["']"#).unwrap();
+    let link = link_regex
+        .find_iter(entry)
+        .map(|link| link.as_str())
+        .find(|link| {
+            rel_regex
+                .captures(link)
+                .is_none_or(|rel| &rel[1] == "alternate")
+        })
+        .and_then(|link| href_regex.captures(link))
+        .map(|href| unescape(&href[1]));
+    link
+}
+
+fn unescape(text: &str) -> String {
+    text.trim()
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn urls(content: &str) -> Vec<String> {
+        parse_urls(content)
+            .unwrap()
+            .into_iter()
+            .map(String::from)
+            .collect()
+    }
+
+    #[test]
+    fn text_links() {
+        let text = "See https://a.example/x?y=1&z=2, and (http://b.example/).\nhttps://a.example/x?y=1&z=2";
+        assert_eq!(
+            urls(text),
+            ["https://a.example/x?y=1&z=2", "http://b.example/"]
+        );
+    }
+
+    #[test]
+    fn sitemap_and_feeds() {
+        let sitemap = "<?xml version=\"1.0\"?><urlset><url><loc> https://a.example/?a=1&amp;b=2 </loc></url></urlset>";
+        assert_eq!(urls(sitemap), ["https://a.example/?a=1&b=2"]);
+
+        let rss = "<rss><channel><link>https://a.example/</link>\
+            <item><link><![CDATA[https://a.example/1]]></link></item></channel></rss>";
+        assert_eq!(urls(rss), ["https://a.example/1"]);
+
+        let atom = r#"<feed><link href="https://a.example/"/><entry>
+            <link rel="self" href="https://a.example/self"/>
+            <link rel="alternate" href="https://a.example/1"/></entry></feed>"#;
+        assert_eq!(urls(atom), ["https://a.example/1"]);
+
+        assert!(parse_urls("<html></html>").is_err());
+    }
+
+    #[test]
+    fn nested_sitemaps_must_be_http() {
+        let index = "<sitemapindex><sitemap><loc>/etc/passwd</loc></sitemap></sitemapindex>";
+        let error = parse_urls(index).unwrap_err().to_string();
+        assert_eq!(error, "/etc/passwd is not an http(s) URL");
+    }
+}
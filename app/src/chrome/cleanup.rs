@@ -0,0 +1,173 @@
+// Built-in removal of cookie consent dialogs and other overlays before capture.
+
+use super::ChromeDriver;
+
+use log::info;
+use serde::Deserialize;
+use serde_json::json;
+
+fn default_enabled() -> bool {
+    true
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CleanupOptions {
+    // Built-in rules for the common consent managers are applied to every
+    // domain unless disabled.
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    // Also removes any fixed element covering most of the page or mentioning
+    // cookies. Opt-in, it can remove the content of pages built that way.
+    #[serde(default)]
+    pub generic: bool,
+    // Additional CSS selectors of elements to click (e.g. "reject all"), and to remove.
+    #[serde(default)]
+    pub click: Vec<String>,
+    #[serde(default)]
+    pub remove: Vec<String>,
+}
+
+impl Default for CleanupOptions {
+    fn default() -> Self {
+        CleanupOptions {
+            enabled: default_enabled(),
+            generic: false,
+            click: vec![],
+            remove: vec![],
+        }
+    }
+}
+
+// Buttons of the common consent managers. Rejecting is preferred, accepting
+// is only tried if there is nothing to reject.
+const REJECT_SELECTORS: &[&str] = &[
+    "#onetrust-reject-all-handler",
+    "#CybotCookiebotDialogBodyButtonDecline",
+    "#didomi-notice-disagree-button",
+    ".qc-cmp2-summary-buttons button[mode=secondary]",
+    "#truste-consent-required",
+    ".fc-cta-do-not-consent",
+    ".cmpboxbtnno",
+];
+const ACCEPT_SELECTORS: &[&str] = &[
+    "#onetrust-accept-btn-handler",
+    "#CybotCookiebotDialogBodyLevelButtonLevelOptinAllowAll",
+    "#didomi-notice-agree-button",
+    ".qc-cmp2-summary-buttons button[mode=primary]",
+    "#truste-consent-button",
+    ".fc-cta-consent",
+    ".cmpboxbtnyes",
+    ".cc-allow",
+    ".cc-dismiss",
+];
+// Containers left behind, also removed if clicking didn't work.
+const REMOVE_SELECTORS: &[&str] = &[
+    "#onetrust-consent-sdk",
+    "#CybotCookiebotDialog",
+    "#CybotCookiebotDialogBodyUnderlay",
+    "#didomi-host",
+    ".qc-cmp2-container",
+    "#truste-consent-track",
+    ".fc-consent-root",
+    "#usercentrics-root",
+    "#cmpbox",
+    "#cmpbox2",
+    ".cc-window",
+    "[id^=sp_message_container]",
+];
+
+const CLEANUP_SCRIPT: &str = r#"
+(function(config) {
+    const summary = {clicked: [], removed: 0};
+    const click = (selectors) => {
+        for (const selector of selectors) {
+            for (const element of document.querySelectorAll(selector)) {
+                if (element.offsetParent !== null) {
+                    element.click();
+                    summary.clicked.push(selector);
+                    return true;
+                }
+            }
+        }
+        return false;
+    };
+    if (!click(config.click) && !click(config.reject)) {
+        click(config.accept);
+    }
+
+    const remove = (element) => { element.remove(); summary.removed++; };
+    for (const selector of config.remove) {
+        document.querySelectorAll(selector).forEach(remove);
+    }
+
+    // Fixed overlays: consent banners by their text, and anything covering
+    // most of the viewport (modal backdrops, newsletter popups).
+    const viewport = window.innerWidth * window.innerHeight;
+    for (const element of config.generic ? document.querySelectorAll('body *') : []) {
+        if (!element.isConnected) {
+            continue;
+        }
+        const position = window.getComputedStyle(element).position;
+        if (position !== 'fixed' && position !== 'sticky') {
+            continue;
+        }
+        const rect = element.getBoundingClientRect();
+        const covering = position === 'fixed' && rect.width * rect.height > viewport * 0.5;
+        const consent = /cookie|consent|gdpr/i.test(element.id + ' ' + element.className)
+            || /cookies/i.test((element.innerText || '').slice(0, 2000));
+        if (covering || consent) {
+            remove(element);
+        }
+    }
+
+    if (summary.clicked.length === 0 && summary.removed === 0) {
+        return summary;
+    }
+    // Dialogs usually disable scrolling while open.
+    for (const element of [document.documentElement, document.body]) {
+        element.style.setProperty('overflow', 'visible', 'important');
+        if (window.getComputedStyle(element).position === 'fixed') {
+            element.style.setProperty('position', 'static', 'important');
+        }
+    }
+    return summary;
+})
+"#;
+
+impl CleanupOptions {
+    pub fn is_active(&self) -> bool {
+        self.enabled || self.generic || !self.click.is_empty() || !self.remove.is_empty()
+    }
+}
+
+impl ChromeDriver {
+    pub fn remove_overlays(&mut self, options: &CleanupOptions) -> anyhow::Result<()> {
+        if !options.is_active() {
+            return Ok(());
+        }
+        let builtin = |selectors: &[&str]| {
+            if options.enabled {
+                selectors.iter().map(|s| s.to_string()).collect()
+            } else {
+                vec![]
+            }
+        };
+        let mut remove: Vec<String> = builtin(REMOVE_SELECTORS);
+        remove.extend(options.remove.iter().cloned());
+        let config = json!({
+            "click": options.click,
+            "reject": builtin(REJECT_SELECTORS),
+            "accept": builtin(ACCEPT_SELECTORS),
+            "remove": remove,
+            "generic": options.generic,
+        });
+        let result = self.evaluate(&format!("{}({})", CLEANUP_SCRIPT, config))?;
+        info!("Overlay cleanup: {}", result);
+        if result["clicked"].as_array().is_some_and(|c| !c.is_empty()) {
+            // Let the consent manager close the dialog and reload content.
+            self.wait(std::time::Duration::from_secs(1))?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,81 @@
+// User stylesheets injected into the captured page, for hiding sidebars,
+// forcing light mode and similar tweaks that are easier to write as CSS.
+
+use super::ChromeDriver;
+
+use log::warn;
+use serde::Deserialize;
+use serde_json::json;
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(untagged)]
+pub enum RenderCss {
+    // "body { background: white !important; }"
+    Inline(String),
+    // {file: "/cfg/example.css"}, read when loading the config.
+    File { file: String },
+}
+
+impl RenderCss {
+    // Replaces a file reference by its content.
+    pub fn load(&mut self) -> anyhow::Result<()> {
+        if let RenderCss::File { file } = self {
+            let css = std::fs::read_to_string(&*file)
+                .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", file, e))?;
+            *self = RenderCss::Inline(css);
+        }
+        Ok(())
+    }
+
+    pub fn css(&self) -> &str {
+        match self {
+            RenderCss::Inline(css) => css,
+            // Not loaded, nothing to inject.
+            RenderCss::File { .. } => "",
+        }
+    }
+}
+
+// Added once the document is parsed, so that the style comes last in the
+// cascade. Still needs !important to win over inline styles.
+const INJECT_SCRIPT: &str = r#"
+(function(css) {
+    const inject = () => {
+        const style = document.createElement('style');
+        style.textContent = css;
+        (document.head || document.documentElement).appendChild(style);
+    };
+    if (document.readyState === 'loading') {
+        document.addEventListener('DOMContentLoaded', inject);
+    } else {
+        inject();
+    }
+})
+"#;
+
+impl ChromeDriver {
+    // Injects the stylesheet into all following navigations, replacing the previous one.
+    pub fn set_stylesheet(&mut self, css: Option<&str>) -> anyhow::Result<()> {
+        if let Some(identifier) = self.stylesheet.take() {
+            // Fails if the browser was restarted in the meantime, nothing to remove then.
+            let params = json!({ "identifier": identifier });
+            if let Err(e) = self.get_result("Page.removeScriptToEvaluateOnNewDocument", params) {
+                warn!("Failed to remove stylesheet: {:?}", e);
+            }
+        }
+        let css = match css {
+            Some(css) if !css.trim().is_empty() => css,
+            _ => return Ok(()),
+        };
+        let source = format!("{}({})", INJECT_SCRIPT, json!(css));
+        let result = self.get_result(
+            "Page.addScriptToEvaluateOnNewDocument",
+            json!({ "source": source }),
+        )?;
+        let identifier = result["identifier"]
+            .as_str()
+            .ok_or_else(|| anyhow::format_err!("Missing script identifier"))?;
+        self.stylesheet = Some(identifier.to_string());
+        Ok(())
+    }
+}
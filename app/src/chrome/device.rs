@@ -0,0 +1,64 @@
+// Device emulation profiles, set per domain and overridable per request.
+
+use serde::Deserialize;
+
+const IPHONE_USER_AGENT: &str = "Mozilla/5.0 (iPhone; CPU iPhone OS 17_5 like Mac OS X) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/17.5 Mobile/15E148 Safari/604.1";
+const TABLET_USER_AGENT: &str = "Mozilla/5.0 (iPad; CPU OS 17_5 like Mac OS X) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/17.5 Mobile/15E148 Safari/604.1";
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeviceName {
+    Desktop,
+    Laptop,
+    Iphone,
+    Tablet,
+}
+
+fn default_scale_factor() -> f64 {
+    1.0
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Device {
+    // Viewport size in CSS pixels.
+    pub width: u32,
+    pub height: u32,
+    #[serde(default = "default_scale_factor")]
+    pub device_scale_factor: f64,
+    #[serde(default)]
+    pub mobile: bool,
+    #[serde(default)]
+    pub touch: bool,
+    // Browser default if unset.
+    pub user_agent: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(untagged)]
+pub enum DeviceProfile {
+    // "desktop", "laptop", "iphone" or "tablet".
+    Named(DeviceName),
+    // {width: 800, height: 600, mobile: true, ...}
+    Custom(Device),
+}
+
+impl DeviceProfile {
+    pub fn device(&self) -> Device {
+        let (width, height, device_scale_factor, user_agent) = match self {
+            DeviceProfile::Custom(device) => return device.clone(),
+            DeviceProfile::Named(DeviceName::Desktop) => (1920, 1080, 1.0, None),
+            DeviceProfile::Named(DeviceName::Laptop) => (1440, 900, 2.0, None),
+            DeviceProfile::Named(DeviceName::Iphone) => (390, 844, 3.0, Some(IPHONE_USER_AGENT)),
+            DeviceProfile::Named(DeviceName::Tablet) => (820, 1180, 2.0, Some(TABLET_USER_AGENT)),
+        };
+        Device {
+            width,
+            height,
+            device_scale_factor,
+            mobile: user_agent.is_some(),
+            touch: user_agent.is_some(),
+            user_agent: user_agent.map(str::to_string),
+        }
+    }
+}
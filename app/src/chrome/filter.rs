@@ -0,0 +1,701 @@
+// EasyList style filter lists for blocking ads and trackers, see
+// https://help.adblockplus.org/hc/en-us/articles/360062733293
+// Only network rules with the common options are supported. Element hiding
+// rules and rules with other options are skipped.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, LazyLock};
+
+use log::info;
+use publicsuffix::Psl;
+use serde::Deserialize;
+
+fn default_enabled() -> bool {
+    true
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BlockingOptions {
+    // The global lists (UDRB_FILTER_LISTS) apply to every domain unless disabled.
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    // Additional filter list files and inline rules for this domain.
+    #[serde(default)]
+    pub lists: Vec<String>,
+    #[serde(default)]
+    pub rules: Vec<String>,
+}
+
+impl Default for BlockingOptions {
+    fn default() -> Self {
+        BlockingOptions {
+            enabled: default_enabled(),
+            lists: vec![],
+            rules: vec![],
+        }
+    }
+}
+
+// Request as seen by the filters.
+pub struct FilterRequest<'a> {
+    pub url: &'a str,
+    // Resource type from the Fetch domain ("Script", "Image", ...).
+    pub resource_type: &'a str,
+    // Host of the captured page.
+    pub page_host: &'a str,
+}
+
+// Returns true if any of the lists blocks the request and none has an exception for it.
+pub fn is_blocked(lists: &[Arc<FilterList>], request: &FilterRequest) -> bool {
+    let url = UrlParts::new(request);
+    lists.iter().any(|l| l.block.matches(&url)) && !lists.iter().any(|l| l.allow.matches(&url))
+}
+
+pub struct FilterList {
+    name: String,
+    block: RuleSet,
+    allow: RuleSet,
+}
+
+impl std::fmt::Debug for FilterList {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "FilterList({}, {} rules, {} exceptions)",
+            self.name, self.block.len, self.allow.len
+        )
+    }
+}
+
+impl FilterList {
+    pub fn load(path: &str) -> anyhow::Result<FilterList> {
+        let text = std::fs::read_to_string(path).map_err(|e| anyhow::anyhow!("{}: {}", path, e))?;
+        Ok(FilterList::parse(path, text.lines()))
+    }
+
+    pub fn parse<'a>(name: &str, lines: impl Iterator<Item = &'a str>) -> FilterList {
+        let mut list = FilterList {
+            name: name.to_string(),
+            block: RuleSet::default(),
+            allow: RuleSet::default(),
+        };
+        let mut skipped = 0;
+        for line in lines.map(str::trim) {
+            // Comments and the "[Adblock Plus 2.0]" header.
+            if line.is_empty() || line.starts_with('!') || line.starts_with('[') {
+                continue;
+            }
+            let (exception, line) = match line.strip_prefix("@@") {
+                Some(line) => (true, line),
+                None => (false, line),
+            };
+            match Rule::parse(line) {
+                Some(rule) if exception => list.allow.add(rule),
+                Some(rule) => list.block.add(rule),
+                None => skipped += 1,
+            }
+        }
+        info!("Loaded {:?}, skipped {} unsupported rules", list, skipped);
+        list
+    }
+}
+
+#[derive(Default)]
+struct RuleSet {
+    // Rules are indexed by a token which must appear in the URL for them to
+    // match, so that only a few of them need to be checked for each request.
+    by_token: HashMap<String, Vec<Rule>>,
+    // Rules without any usable token.
+    generic: Vec<Rule>,
+    len: usize,
+}
+
+impl RuleSet {
+    fn add(&mut self, rule: Rule) {
+        self.len += 1;
+        match rule.token() {
+            Some(token) => self.by_token.entry(token).or_default().push(rule),
+            None => self.generic.push(rule),
+        }
+    }
+
+    fn matches(&self, url: &UrlParts) -> bool {
+        url.tokens
+            .iter()
+            .filter_map(|token| self.by_token.get(token))
+            .flatten()
+            .chain(self.generic.iter())
+            .any(|rule| rule.matches(url))
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Anchor {
+    None,
+    // "|http://..."
+    Start,
+    // "||example.com^", matches the domain and its subdomains.
+    Domain,
+}
+
+enum Pattern {
+    Glob {
+        text: String,
+        start: Anchor,
+        end: bool,
+    },
+    Regex(regex::Regex),
+}
+
+struct Rule {
+    pattern: Pattern,
+    match_case: bool,
+    third_party: Option<bool>,
+    // Filter option names of the resource types, all types if empty.
+    types: Vec<&'static str>,
+    excluded_types: Vec<&'static str>,
+    // Page domains the rule applies to, all if empty.
+    domains: Vec<String>,
+    excluded_domains: Vec<String>,
+}
+
+const RESOURCE_TYPES: &[&str] = &[
+    "script",
+    "image",
+    "stylesheet",
+    "xmlhttprequest",
+    "subdocument",
+    "font",
+    "media",
+    "ping",
+    "websocket",
+    "object",
+    "other",
+];
+
+fn resource_type(option: &str) -> Option<&'static str> {
+    let option = match option {
+        "xhr" => "xmlhttprequest",
+        "css" => "stylesheet",
+        "frame" => "subdocument",
+        "beacon" => "ping",
+        option => option,
+    };
+    RESOURCE_TYPES.iter().find(|t| **t == option).copied()
+}
+
+// Maps the Fetch domain resource type to the filter option name.
+fn filter_resource_type(resource_type: &str) -> &'static str {
+    match resource_type {
+        "Script" => "script",
+        "Image" => "image",
+        "Stylesheet" => "stylesheet",
+        "XHR" | "Fetch" | "EventSource" => "xmlhttprequest",
+        "Document" => "subdocument",
+        "Font" => "font",
+        "Media" => "media",
+        "Ping" | "CSPViolationReport" => "ping",
+        "WebSocket" => "websocket",
+        _ => "other",
+    }
+}
+
+fn is_token_char(c: u8) -> bool {
+    c.is_ascii_alphanumeric()
+}
+
+// Characters matched by "^".
+fn is_separator(c: u8) -> bool {
+    !c.is_ascii_alphanumeric() && !matches!(c, b'_' | b'-' | b'.' | b'%')
+}
+
+// Anchored match with "*" (anything) and "^" (separator or end). Classic
+// wildcard matching, backtracking only to the last star.
+fn glob_match(pattern: &[u8], text: &[u8]) -> bool {
+    let (mut p, mut t) = (0, 0);
+    let mut star = None;
+    loop {
+        match (pattern.get(p), text.get(t)) {
+            (Some(b'*'), _) => {
+                star = Some((p, t));
+                p += 1;
+                continue;
+            }
+            (Some(b'^'), Some(c)) if is_separator(*c) => {
+                p += 1;
+                t += 1;
+                continue;
+            }
+            (Some(b'^'), None) => {
+                p += 1;
+                continue;
+            }
+            (Some(c), Some(d)) if *c != b'^' && c == d => {
+                p += 1;
+                t += 1;
+                continue;
+            }
+            (None, None) => return true,
+            _ => {}
+        }
+        match star {
+            Some((star_p, star_t)) if star_t < text.len() => {
+                p = star_p + 1;
+                t = star_t + 1;
+                star = Some((star_p, star_t + 1));
+            }
+            _ => return false,
+        }
+    }
+}
+
+// Copy of https://publicsuffix.org/list/public_suffix_list.dat, new suffixes
+// are rare enough for it to be updated by hand once in a while.
+static PUBLIC_SUFFIXES: LazyLock<publicsuffix::List> = LazyLock::new(|| {
+    include_str!("../../data/public_suffix_list.dat")
+        .parse()
+        .expect("Invalid public suffix list")
+});
+
+// Registrable domain ("example.co.uk" for "www.example.co.uk"), for telling
+// first and third party requests apart. IP addresses and hosts without a known
+// suffix are used as is.
+fn base_domain(host: &str) -> &str {
+    if host.parse::<std::net::IpAddr>().is_ok() || host.starts_with('[') {
+        return host;
+    }
+    match PUBLIC_SUFFIXES.domain(host.as_bytes()) {
+        Some(domain) => &host[host.len() - domain.as_bytes().len()..],
+        None => host,
+    }
+}
+
+fn is_subdomain(host: &str, domain: &str) -> bool {
+    host == domain
+        || (host.ends_with(domain) && host.as_bytes()[host.len() - domain.len() - 1] == b'.')
+}
+
+struct UrlParts<'a> {
+    url: &'a str,
+    lowercase: String,
+    // Offsets where a domain anchor can match: start of the host and after each dot in it.
+    domain_starts: Vec<usize>,
+    tokens: HashSet<String>,
+    resource_type: &'static str,
+    third_party: bool,
+    page_host: &'a str,
+}
+
+impl<'a> UrlParts<'a> {
+    fn new(request: &FilterRequest<'a>) -> UrlParts<'a> {
+        let url = request.url;
+        let host_start = url.find("://").map_or(0, |i| i + 3);
+        let host_end = url[host_start..]
+            .find(['/', ':', '?', '#'])
+            .map_or(url.len(), |i| host_start + i);
+        let host = &url[host_start..host_end];
+        let mut domain_starts = vec![host_start];
+        domain_starts.extend(host.match_indices('.').map(|(i, _)| host_start + i + 1));
+        let lowercase = url.to_lowercase();
+        UrlParts {
+            url,
+            // Tokens are matched case insensitively, see Rule::token.
+            tokens: lowercase
+                .split(|c: char| !c.is_ascii_alphanumeric())
+                .filter(|t| !t.is_empty())
+                .map(str::to_string)
+                .collect(),
+            lowercase,
+            domain_starts,
+            resource_type: filter_resource_type(request.resource_type),
+            third_party: base_domain(&host.to_lowercase()) != base_domain(request.page_host),
+            page_host: request.page_host,
+        }
+    }
+}
+
+impl Rule {
+    fn parse(line: &str) -> Option<Rule> {
+        // Element hiding rules.
+        if line.contains("##")
+            || line.contains("#@#")
+            || line.contains("#?#")
+            || line.contains("#$#")
+        {
+            return None;
+        }
+        let (pattern, options) = match line.rsplit_once('$') {
+            // "$" can also be a part of a regex rule.
+            Some((pattern, options)) if !options.contains('/') => (pattern, Some(options)),
+            _ => (line, None),
+        };
+
+        let mut rule = Rule {
+            pattern: Pattern::Glob {
+                text: String::new(),
+                start: Anchor::None,
+                end: false,
+            },
+            match_case: false,
+            third_party: None,
+            types: vec![],
+            excluded_types: vec![],
+            domains: vec![],
+            excluded_domains: vec![],
+        };
+        for option in options.into_iter().flat_map(|o| o.split(',')) {
+            let (negated, name) = match option.strip_prefix('~') {
+                Some(name) => (true, name),
+                None => (false, option),
+            };
+            match name {
+                "third-party" | "3p" => rule.third_party = Some(!negated),
+                "first-party" | "1p" => rule.third_party = Some(negated),
+                "match-case" => rule.match_case = true,
+                // Exceptions override blocking rules regardless.
+                "important" => {}
+                _ if name.starts_with("domain=") && !negated => {
+                    for domain in name["domain=".len()..].split('|') {
+                        match domain.strip_prefix('~') {
+                            Some(domain) => rule.excluded_domains.push(domain.to_lowercase()),
+                            None => rule.domains.push(domain.to_lowercase()),
+                        }
+                    }
+                }
+                _ => match resource_type(name) {
+                    Some(t) if negated => rule.excluded_types.push(t),
+                    Some(t) => rule.types.push(t),
+                    // Anything else would need more than blocking the request.
+                    None => return None,
+                },
+            }
+        }
+
+        rule.pattern = if pattern.len() > 2 && pattern.starts_with('/') && pattern.ends_with('/') {
+            let regex = regex::RegexBuilder::new(&pattern[1..pattern.len() - 1])
+                .case_insensitive(!rule.match_case)
+                .build()
+                .ok()?;
+            Pattern::Regex(regex)
+        } else {
+            let (start, text) = if let Some(text) = pattern.strip_prefix("||") {
+                (Anchor::Domain, text)
+            } else if let Some(text) = pattern.strip_prefix('|') {
+                (Anchor::Start, text)
+            } else {
+                (Anchor::None, pattern.trim_start_matches('*'))
+            };
+            let (end, text) = match text.strip_suffix('|') {
+                Some(text) => (true, text),
+                None => (false, text.trim_end_matches('*')),
+            };
+            let text = if rule.match_case {
+                text.to_string()
+            } else {
+                text.to_lowercase()
+            };
+            Pattern::Glob { text, start, end }
+        };
+        Some(rule)
+    }
+
+    // Longest alphanumeric run of the pattern which must be a whole token in
+    // any matching URL, i.e. it's not next to a wildcard or unanchored start or end.
+    fn token(&self) -> Option<String> {
+        let (text, start, end) = match &self.pattern {
+            Pattern::Glob { text, start, end } => (text.as_bytes(), *start, *end),
+            Pattern::Regex(_) => return None,
+        };
+        let mut best: Option<&[u8]> = None;
+        let mut i = 0;
+        while i < text.len() {
+            if !is_token_char(text[i]) {
+                i += 1;
+                continue;
+            }
+            let begin = i;
+            while i < text.len() && is_token_char(text[i]) {
+                i += 1;
+            }
+            let left = if begin == 0 {
+                start != Anchor::None
+            } else {
+                text[begin - 1] != b'*'
+            };
+            let right = if i == text.len() {
+                end
+            } else {
+                text[i] != b'*'
+            };
+            if left && right && best.is_none_or(|b| b.len() < i - begin) {
+                best = Some(&text[begin..i]);
+            }
+        }
+        // URL tokens are lowercased.
+        best.map(|t| String::from_utf8_lossy(t).to_lowercase())
+    }
+
+    fn matches(&self, url: &UrlParts) -> bool {
+        if self.third_party.is_some_and(|t| t != url.third_party) {
+            return false;
+        }
+        if !self.types.is_empty() && !self.types.contains(&url.resource_type) {
+            return false;
+        }
+        if self.excluded_types.contains(&url.resource_type) {
+            return false;
+        }
+        let page_host = url.page_host;
+        if !self.domains.is_empty() && !self.domains.iter().any(|d| is_subdomain(page_host, d)) {
+            return false;
+        }
+        if self
+            .excluded_domains
+            .iter()
+            .any(|d| is_subdomain(page_host, d))
+        {
+            return false;
+        }
+
+        let (text, start, end) = match &self.pattern {
+            Pattern::Regex(regex) => return regex.is_match(url.url),
+            Pattern::Glob { text, start, end } => (text, *start, *end),
+        };
+        let target = if self.match_case {
+            url.url
+        } else {
+            &url.lowercase
+        };
+        let mut pattern = Vec::with_capacity(text.len() + 2);
+        if start == Anchor::None {
+            pattern.push(b'*');
+        }
+        pattern.extend_from_slice(text.as_bytes());
+        if !end {
+            pattern.push(b'*');
+        }
+        match start {
+            Anchor::Domain => url
+                .domain_starts
+                .iter()
+                .any(|i| glob_match(&pattern, &target.as_bytes()[*i..])),
+            _ => glob_match(&pattern, target.as_bytes()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn list(rules: &[&str]) -> Arc<FilterList> {
+        Arc::new(FilterList::parse("test", rules.iter().copied()))
+    }
+
+    fn blocked(lists: &[Arc<FilterList>], url: &str, resource_type: &str, page_host: &str) -> bool {
+        is_blocked(
+            lists,
+            &FilterRequest {
+                url,
+                resource_type,
+                page_host,
+            },
+        )
+    }
+
+    #[test]
+    fn glob() {
+        assert!(glob_match(b"ads", b"ads"));
+        assert!(!glob_match(b"ads", b"ads/"));
+        assert!(glob_match(b"*ads*", b"https://x.com/ads/1"));
+        assert!(glob_match(b"a*c*e", b"abcde"));
+        assert!(!glob_match(b"a*c*e", b"abcd"));
+        // "^" matches a separator or the end, but not "." "-" "_" "%".
+        assert!(glob_match(b"ads^*", b"ads/x"));
+        assert!(glob_match(b"ads^*", b"ads?x"));
+        assert!(glob_match(b"ads^", b"ads"));
+        assert!(!glob_match(b"ads^*", b"ads.js"));
+        assert!(!glob_match(b"ads^*", b"ads-x"));
+    }
+
+    #[test]
+    fn anchors() {
+        let lists = [list(&["|https://start.com/", "/end.js|", "||ads.com^"])];
+        assert!(blocked(&lists, "https://start.com/x", "Script", "a.com"));
+        assert!(!blocked(
+            &lists,
+            "http://x.com/?https://start.com/",
+            "Script",
+            "a.com"
+        ));
+        assert!(blocked(&lists, "https://x.com/end.js", "Script", "a.com"));
+        assert!(!blocked(
+            &lists,
+            "https://x.com/end.js?v=1",
+            "Script",
+            "a.com"
+        ));
+        // Domain anchors match the domain and its subdomains only.
+        assert!(blocked(&lists, "https://ads.com/x.js", "Script", "a.com"));
+        assert!(blocked(
+            &lists,
+            "https://cdn.ads.com:8080/x.js",
+            "Script",
+            "a.com"
+        ));
+        assert!(!blocked(
+            &lists,
+            "https://badads.com/x.js",
+            "Script",
+            "a.com"
+        ));
+        assert!(!blocked(
+            &lists,
+            "https://ads.com.evil.org/",
+            "Script",
+            "a.com"
+        ));
+        assert!(!blocked(
+            &lists,
+            "https://x.com/?u=ads.com/",
+            "Script",
+            "a.com"
+        ));
+    }
+
+    #[test]
+    fn tokens() {
+        let token = |rule: &str| Rule::parse(rule).unwrap().token();
+        assert_eq!(token("||ads.example.com^").as_deref(), Some("example"));
+        assert_eq!(token("/banner/*/img").as_deref(), Some("banner"));
+        // Unanchored ends can be a part of a longer URL token.
+        assert_eq!(token("tracker"), None);
+        assert_eq!(token("-tracker-").as_deref(), Some("tracker"));
+        assert_eq!(token("|https://Track.com|").as_deref(), Some("https"));
+        assert_eq!(token("/ad[0-9]+/"), None);
+        // Rules are found through their token, case insensitively.
+        let lists = [list(&["/Banner/*/img", "-tracker-"])];
+        assert!(blocked(
+            &lists,
+            "https://x.com/banner/1/img.png",
+            "Image",
+            "x.com"
+        ));
+        assert!(blocked(
+            &lists,
+            "https://x.com/a-TRACKER-b",
+            "Image",
+            "x.com"
+        ));
+    }
+
+    #[test]
+    fn options() {
+        let lists = [list(&[
+            "||cdn.com^$third-party",
+            "/pixel.$image,domain=news.com|~sport.news.com",
+            "/widget.js$~script",
+            "$websocket",
+        ])];
+        assert!(blocked(
+            &lists,
+            "https://cdn.com/a.js",
+            "Script",
+            "news.com"
+        ));
+        assert!(!blocked(
+            &lists,
+            "https://cdn.com/a.js",
+            "Script",
+            "www.cdn.com"
+        ));
+        assert!(blocked(
+            &lists,
+            "https://x.com/pixel.gif",
+            "Image",
+            "www.news.com"
+        ));
+        assert!(!blocked(
+            &lists,
+            "https://x.com/pixel.gif",
+            "Image",
+            "sport.news.com"
+        ));
+        assert!(!blocked(
+            &lists,
+            "https://x.com/pixel.gif",
+            "Image",
+            "other.com"
+        ));
+        assert!(!blocked(
+            &lists,
+            "https://x.com/pixel.gif",
+            "Script",
+            "news.com"
+        ));
+        assert!(blocked(&lists, "https://x.com/widget.js", "Image", "a.com"));
+        assert!(!blocked(
+            &lists,
+            "https://x.com/widget.js",
+            "Script",
+            "a.com"
+        ));
+        assert!(blocked(&lists, "wss://x.com/socket", "WebSocket", "a.com"));
+        // Unsupported options skip the rule rather than blocking more.
+        assert!(Rule::parse("||x.com^$popup").is_none());
+        assert!(Rule::parse("example.com##.ad").is_none());
+    }
+
+    #[test]
+    fn exceptions() {
+        let lists = [
+            list(&["||ads.com^"]),
+            list(&["@@||ads.com/allowed/$script"]),
+        ];
+        assert!(blocked(&lists, "https://ads.com/x.js", "Script", "a.com"));
+        assert!(!blocked(
+            &lists,
+            "https://ads.com/allowed/x.js",
+            "Script",
+            "a.com"
+        ));
+        assert!(blocked(
+            &lists,
+            "https://ads.com/allowed/x.png",
+            "Image",
+            "a.com"
+        ));
+    }
+
+    #[test]
+    fn third_party() {
+        assert_eq!(base_domain("www.nzz.ch"), "nzz.ch");
+        assert_eq!(base_domain("img.nzz.ch"), "nzz.ch");
+        assert_eq!(base_domain("tvthek.orf.at"), "orf.at");
+        assert_eq!(base_domain("www.bbc.co.uk"), "bbc.co.uk");
+        assert_eq!(base_domain("user.github.io"), "user.github.io");
+        assert_eq!(base_domain("127.0.0.1"), "127.0.0.1");
+        assert_eq!(base_domain("localhost"), "localhost");
+
+        let lists = [list(&["||nzz.ch^$third-party", "||github.io^$third-party"])];
+        assert!(!blocked(
+            &lists,
+            "https://img.nzz.ch/a.jpg",
+            "Image",
+            "www.nzz.ch"
+        ));
+        assert!(blocked(
+            &lists,
+            "https://img.nzz.ch/a.jpg",
+            "Image",
+            "www.orf.at"
+        ));
+        assert!(blocked(
+            &lists,
+            "https://a.github.io/x.js",
+            "Script",
+            "b.github.io"
+        ));
+    }
+}
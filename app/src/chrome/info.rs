@@ -3,7 +3,7 @@
 use markup5ever_rcdom as rcdom;
 use std::collections::HashMap;
 
-#[derive(Debug)]
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub struct PageInfo {
     pub icon: Option<String>,
     pub description: Option<String>,
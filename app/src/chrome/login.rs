@@ -0,0 +1,260 @@
+// Declarative multi-step login flows, for logins that don't fit a single page and script.
+
+use super::{ChromeDriver, ScriptError, TotpOptions};
+
+use std::time::{Duration, Instant};
+
+use log::info;
+use serde::Deserialize;
+use serde_json::json;
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LoginAction {
+    // Navigates to the URL and waits for it to load.
+    Goto(String),
+    WaitForSelector(String),
+    // Types the text into the element, key by key.
+    Type { selector: String, text: String },
+    Click(String),
+    // Waits for a navigation started by one of the previous steps to finish loading.
+    WaitForNavigation,
+    // Runs the script, awaiting the returned promise.
+    Eval(String),
+    // Types the current TOTP code of the domain into the element.
+    TypeTotp(String),
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(try_from = "RawLoginStep")]
+pub struct LoginStep {
+    pub action: LoginAction,
+    // Time to wait for the element or navigation, 10 seconds if unset.
+    pub timeout_ms: Option<u64>,
+}
+
+// deny_unknown_fields doesn't work with flatten, the keys left over after the
+// action are checked instead, so that typos like timout_ms are not ignored.
+#[derive(Deserialize)]
+struct RawLoginStep {
+    #[serde(flatten)]
+    action: LoginAction,
+    timeout_ms: Option<u64>,
+    #[serde(flatten)]
+    unknown: std::collections::BTreeMap<String, serde_yaml::Value>,
+}
+
+impl TryFrom<RawLoginStep> for LoginStep {
+    type Error = String;
+
+    fn try_from(raw: RawLoginStep) -> Result<Self, Self::Error> {
+        match raw.unknown.keys().next() {
+            Some(key) => Err(format!(
+                "unknown field `{}`, expected one action and timeout_ms",
+                key
+            )),
+            None => Ok(LoginStep {
+                action: raw.action,
+                timeout_ms: raw.timeout_ms,
+            }),
+        }
+    }
+}
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+// TOTP codes valid for less than this are not used, the next one is awaited instead.
+const TOTP_MIN_VALIDITY: u64 = 3;
+
+impl std::fmt::Display for LoginAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        use LoginAction::*;
+        match self {
+            Goto(url) => write!(f, "goto {}", url),
+            WaitForSelector(selector) => write!(f, "wait_for_selector {:?}", selector),
+            // The text is likely a password.
+            Type { selector, .. } => write!(f, "type into {:?}", selector),
+            Click(selector) => write!(f, "click {:?}", selector),
+            WaitForNavigation => write!(f, "wait_for_navigation"),
+            Eval(_) => write!(f, "eval"),
+            TypeTotp(selector) => write!(f, "type_totp into {:?}", selector),
+        }
+    }
+}
+
+// Failed login step, reported to the user as is.
+#[derive(Debug)]
+pub struct LoginError {
+    // 1-based index of the failed step.
+    pub step: usize,
+    pub action: String,
+    pub error: String,
+}
+
+impl std::fmt::Display for LoginError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "step {} ({}): {}", self.step, self.action, self.error)
+    }
+}
+
+impl std::error::Error for LoginError {}
+
+// Step didn't find what it was waiting for, as opposed to browser failures.
+#[derive(Debug)]
+struct StepError(String);
+
+impl std::fmt::Display for StepError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for StepError {}
+
+impl ChromeDriver {
+    pub fn run_login_steps(
+        &mut self,
+        steps: &[LoginStep],
+        totp: Option<&TotpOptions>,
+    ) -> anyhow::Result<()> {
+        // Load events are needed to wait for navigations.
+        self.get_result("Page.enable", serde_json::Value::Null)?;
+        // Loads seen before the last step that could have navigated.
+        let mut loads = self.page_loads;
+        for (i, step) in steps.iter().enumerate() {
+            info!("Login step {}: {}", i + 1, step.action);
+            let timeout = step
+                .timeout_ms
+                .map_or(DEFAULT_TIMEOUT, Duration::from_millis);
+            if !matches!(step.action, LoginAction::WaitForNavigation) {
+                loads = self.page_loads;
+            }
+            let result = self.run_login_action(&step.action, timeout, loads, totp);
+            if let Err(e) = result {
+                // Browser failures are not the fault of the step.
+                if e.is::<ScriptError>() || e.is::<StepError>() {
+                    return Err(LoginError {
+                        step: i + 1,
+                        action: step.action.to_string(),
+                        error: e.to_string(),
+                    }
+                    .into());
+                }
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    fn run_login_action(
+        &mut self,
+        action: &LoginAction,
+        timeout: Duration,
+        loads: u64,
+        totp: Option<&TotpOptions>,
+    ) -> anyhow::Result<()> {
+        use LoginAction::*;
+        match action {
+            Goto(url) => {
+                let result = self.get_result("Page.navigate", json!({ "url": url }))?;
+                if let Some(error) = result["errorText"].as_str() {
+                    return Err(StepError(error.to_string()).into());
+                }
+                self.wait_for_load(loads, timeout)
+            }
+            WaitForSelector(selector) => self.wait_for_selector(selector, timeout).map(|_| ()),
+            Type { selector, text } => self.type_text(selector, text, timeout),
+            Click(selector) => {
+                let node_id = self.wait_for_selector(selector, timeout)?;
+                let (x, y) = self.element_center(node_id)?;
+                for event in ["mousePressed", "mouseReleased"] {
+                    let params = json!({
+                        "type": event, "x": x, "y": y, "button": "left", "clickCount": 1,
+                    });
+                    self.get_result("Input.dispatchMouseEvent", params)?;
+                }
+                Ok(())
+            }
+            WaitForNavigation => self.wait_for_load(loads, timeout),
+            Eval(script) => self.execute(script).map(|_| ()),
+            TypeTotp(selector) => {
+                let totp =
+                    totp.ok_or_else(|| StepError("No totp configured for the domain".to_string()))?;
+                // Wait for the field first, the code should be fresh when typed.
+                self.wait_for_selector(selector, timeout)?;
+                let (mut code, valid_for) = totp.code();
+                if valid_for < TOTP_MIN_VALIDITY {
+                    self.wait(Duration::from_secs(valid_for))?;
+                    code = totp.code().0;
+                }
+                self.type_text(selector, &code, timeout)
+            }
+        }
+    }
+
+    // Focuses the element and types the text key by key.
+    fn type_text(&mut self, selector: &str, text: &str, timeout: Duration) -> anyhow::Result<()> {
+        let node_id = self.wait_for_selector(selector, timeout)?;
+        self.get_result("DOM.focus", json!({ "nodeId": node_id }))?;
+        for c in text.chars() {
+            let params = json!({"type": "keyDown", "text": c.to_string()});
+            self.get_result("Input.dispatchKeyEvent", params)?;
+            self.get_result("Input.dispatchKeyEvent", json!({"type": "keyUp"}))?;
+        }
+        Ok(())
+    }
+
+    // Waits until the page fires a load event after the given count.
+    fn wait_for_load(&mut self, loads: u64, timeout: Duration) -> anyhow::Result<()> {
+        let deadline = Instant::now() + timeout;
+        while self.page_loads <= loads {
+            if Instant::now() >= deadline {
+                return Err(StepError(format!("No page load within {:?}", timeout)).into());
+            }
+            self.wait(POLL_INTERVAL)?;
+        }
+        Ok(())
+    }
+
+    // Returns the DOM node id of the first element matching the selector.
+    fn wait_for_selector(&mut self, selector: &str, timeout: Duration) -> anyhow::Result<u64> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            // Node ids are invalidated by navigations, so always start from a fresh document.
+            let document = self.get_result("DOM.getDocument", json!({ "depth": 0 }))?;
+            let params = json!({"nodeId": document["root"]["nodeId"], "selector": selector});
+            let node_id = self
+                .get_result("DOM.querySelector", params)
+                .map_err(|e| StepError(e.to_string()))?["nodeId"]
+                .as_u64()
+                .unwrap_or_default();
+            if node_id != 0 {
+                return Ok(node_id);
+            }
+            if Instant::now() >= deadline {
+                return Err(StepError(format!("Element not found within {:?}", timeout)).into());
+            }
+            self.wait(POLL_INTERVAL)?;
+        }
+    }
+
+    // Scrolls the element into view and returns its center in viewport coordinates.
+    fn element_center(&mut self, node_id: u64) -> anyhow::Result<(f64, f64)> {
+        let params = json!({ "nodeId": node_id });
+        self.get_result("DOM.scrollIntoViewIfNeeded", params.clone())?;
+        let result = self
+            .get_result("DOM.getContentQuads", params)
+            .map_err(|e| StepError(format!("Element is not visible ({})", e)))?;
+        // Quad is four x, y corner points.
+        let quad: Vec<f64> = result["quads"][0]
+            .as_array()
+            .ok_or_else(|| StepError("Element is not visible".to_string()))?
+            .iter()
+            .filter_map(serde_json::Value::as_f64)
+            .collect();
+        anyhow::ensure!(quad.len() == 8, "Invalid quad {:?}", quad);
+        let x = quad.iter().step_by(2).sum::<f64>() / 4.0;
+        let y = quad.iter().skip(1).step_by(2).sum::<f64>() / 4.0;
+        Ok((x, y))
+    }
+}
@@ -1,24 +1,123 @@
+mod cleanup;
+mod css;
+mod device;
+mod filter;
 mod info;
+mod login;
+mod pdf;
+mod reader;
+mod screenshot;
+mod scroll;
+mod thumbnail;
+mod totp;
+pub use cleanup::CleanupOptions;
+pub use css::RenderCss;
+pub use device::{Device, DeviceProfile};
+use filter::{is_blocked, FilterRequest};
+pub use filter::{BlockingOptions, FilterList};
 pub use info::PageInfo;
+pub use login::{LoginError, LoginStep};
+pub use pdf::{PdfMode, PdfOptions};
+pub use reader::reading_time_minutes;
+use reader::{page_text, Article};
+pub use screenshot::ScreenshotOptions;
+pub use scroll::ScrollOptions;
+pub use thumbnail::save_thumbnail;
+pub use totp::TotpOptions;
 
 use std::fs::File;
 use std::io::Write;
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant};
 
 use anyhow::format_err;
 use base64::Engine;
-use log::{info, warn};
+use log::{debug, info, warn};
 use serde_json::json;
 use sha3::Digest;
-use websocket::client::sync::Client;
 use websocket::stream::sync::TcpStream;
+use websocket::sync::Writer;
+use websocket::OwnedMessage;
+
+// Files (relative to the output directory) and metadata extracted from MHTML snapshot.
+#[derive(Debug)]
+pub struct Snapshot {
+    pub index_file: String,
+    pub page_info: Option<PageInfo>,
+    pub article: Option<ArticleFiles>,
+    // Article text if available, otherwise text of the whole page.
+    pub text: Option<String>,
+}
+
+// Reader view of the page, see reader.rs.
+#[derive(Debug)]
+pub struct ArticleFiles {
+    pub html_file: String,
+    pub markdown_file: String,
+    pub text_file: String,
+    pub word_count: usize,
+}
+
+// Messages are read on a separate thread, so that waiting for them can time out.
+struct Connection {
+    writer: Writer<TcpStream>,
+    messages: mpsc::Receiver<OwnedMessage>,
+}
+
+// Request blocking for the current page, see set_blocking.
+struct Blocking {
+    lists: Vec<Arc<FilterList>>,
+    page_host: String,
+    main_frame_id: String,
+    blocked: usize,
+}
 
 pub struct ChromeDriver {
     address: String,
     kill_address: String,
-    ws: Option<Client<TcpStream>>,
+    ws: Option<Connection>,
     message_id: u32,
+    // Emulated device of the current request, see emulate_device.
+    device: Option<Device>,
+    // User agent of the browser itself, used when the device doesn't set one.
+    default_user_agent: Option<String>,
+    blocking: Option<Blocking>,
+    // Script injecting the domain stylesheet, see set_stylesheet.
+    stylesheet: Option<String>,
+    // Number of page load events, see run_login_steps.
+    page_loads: u64,
 }
 
+// Exception thrown by a page script, as opposed to failures of the browser itself.
+#[derive(Debug)]
+pub struct ScriptError(pub String);
+
+impl std::fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for ScriptError {}
+
+// Fails with the exception message if the Runtime.evaluate result has one.
+fn check_exception(result: &serde_json::Value) -> Result<(), ScriptError> {
+    let details = match result.get("exceptionDetails") {
+        Some(details) => details,
+        None => return Ok(()),
+    };
+    // Description is the error with the stack trace, only the first line is interesting.
+    let message = details["exception"]["description"]
+        .as_str()
+        .and_then(|d| d.lines().next())
+        .or(details["text"].as_str())
+        .unwrap_or("Unknown exception");
+    Err(ScriptError(message.to_string()))
+}
+
+// Time for scripts not returning a promise to take effect.
+const SCRIPT_SETTLE_TIME: Duration = Duration::from_secs(3);
+
 #[derive(serde::Serialize)]
 struct ChromeCommandRequest {
     id: u32,
@@ -39,13 +138,21 @@ fn write_base64_to_directory(
 ) -> anyhow::Result<String> {
     let bytes = base64::engine::general_purpose::STANDARD.decode(data)?;
     let filename = bytes_to_hash(&bytes) + suffix;
-    let output_path = dir.join(&filename);
+    write_bytes_to_directory(&bytes, dir, &filename)?;
+    Ok(filename)
+}
+
+fn write_bytes_to_directory(
+    bytes: &[u8],
+    dir: &std::path::Path,
+    filename: &str,
+) -> anyhow::Result<()> {
+    let output_path = dir.join(filename);
 
     std::fs::create_dir_all(dir)?;
     let mut buffer = File::create(output_path)?;
-    buffer.write_all(&bytes)?;
-
-    Ok(filename)
+    buffer.write_all(bytes)?;
+    Ok(())
 }
 
 fn get_extension(headers: &[mail_parser::Header]) -> Option<&'static str> {
@@ -113,10 +220,24 @@ fn get_content_location(headers: &[mail_parser::Header]) -> Option<String> {
     None
 }
 
-fn write_mhtml_to_directory(
-    data: &str,
+fn write_article_to_directory(
+    article: &Article,
     dir: &std::path::Path,
-) -> anyhow::Result<(String, Option<PageInfo>)> {
+    hash: &str,
+) -> anyhow::Result<ArticleFiles> {
+    std::fs::write(dir.join("article.html"), &article.html)?;
+    std::fs::write(dir.join("article.md"), &article.markdown)?;
+    std::fs::write(dir.join("article.txt"), &article.text)?;
+    info!("Wrote {}/article.*", hash);
+    Ok(ArticleFiles {
+        html_file: format!("{}/article.html", hash),
+        markdown_file: format!("{}/article.md", hash),
+        text_file: format!("{}/article.txt", hash),
+        word_count: article.word_count,
+    })
+}
+
+fn write_mhtml_to_directory(data: &str, dir: &std::path::Path) -> anyhow::Result<Snapshot> {
     let message = mail_parser::MessageParser::default()
         .parse(data.as_bytes())
         .ok_or(format_err!("Failed to parse mhtml"))?;
@@ -172,6 +293,8 @@ fn write_mhtml_to_directory(
     let index_path = dir.join("index.html");
     let mut index_file = File::create(index_path)?;
     let page_info;
+    let article;
+    let text;
     if let mail_parser::PartType::Html(html) = &message.parts[1].body {
         let mut html = html.to_string();
         for (content_location, filename) in part_filenames {
@@ -179,11 +302,30 @@ fn write_mhtml_to_directory(
         }
         index_file.write_all(html.as_bytes())?;
         page_info = PageInfo::from_html(&html).ok();
+        // Not every page is an article, failing to extract one is fine.
+        match Article::from_html(&html) {
+            Ok(extracted) => {
+                article = write_article_to_directory(&extracted, &dir, &hash)
+                    .map_err(|e| warn!("Failed to write article: {:?}", e))
+                    .ok();
+                text = Some(extracted.text);
+            }
+            Err(e) => {
+                info!("No article extracted: {:?}", e);
+                article = None;
+                text = page_text(&html).ok();
+            }
+        }
     } else {
         return Err(format_err!("Unexpected body for index"));
     }
 
-    Ok((format!("{}/index.html", hash), page_info))
+    Ok(Snapshot {
+        index_file: format!("{}/index.html", hash),
+        page_info,
+        article,
+        text,
+    })
 }
 
 // TODO: Rewrite in a way that is robust to Chrome hanging or dieing. Something like:
@@ -198,6 +340,11 @@ impl ChromeDriver {
             kill_address: kill_address.to_string(),
             ws: None,
             message_id: 0,
+            device: None,
+            default_user_agent: None,
+            blocking: None,
+            stylesheet: None,
+            page_loads: 0,
         };
         // Connect to return error early if misconfigured.
         // TODO: This can fail because chrome might not be ready yet on "docker compose up".
@@ -233,6 +380,7 @@ impl ChromeDriver {
                 .ws
                 .as_mut()
                 .ok_or(format_err!("Lost socket"))?
+                .writer
                 .send_message(&message);
             if send_result.is_ok() {
                 // We managed to send something. Ignore the reply, just return as we have a valid connection.
@@ -241,6 +389,10 @@ impl ChromeDriver {
         }
         // If we have no socket or sending failed we need to establish new connection.
         info!("Restarting chrome connection...");
+        if let Some(connection) = self.ws.take() {
+            // Stops the reader thread.
+            let _ = connection.writer.shutdown_all();
+        }
 
         // Chrome only allows connection when the host header is either
         // localhost or IP, so the "chrome:port" value from docker compose
@@ -261,7 +413,17 @@ impl ChromeDriver {
         let websocket_url = list[0]["webSocketDebuggerUrl"]
             .as_str()
             .ok_or_else(|| format_err!("Invalid websocket url"))?;
-        self.ws = Some(websocket::ClientBuilder::new(websocket_url)?.connect_insecure()?);
+        let client = websocket::ClientBuilder::new(websocket_url)?.connect_insecure()?;
+        let (mut reader, writer) = client.split()?;
+        let (sender, messages) = mpsc::channel();
+        std::thread::spawn(move || {
+            while let Ok(message) = reader.recv_message() {
+                if sender.send(message).is_err() {
+                    break;
+                }
+            }
+        });
+        self.ws = Some(Connection { writer, messages });
         Ok(())
     }
 
@@ -279,11 +441,31 @@ impl ChromeDriver {
         self.ws
             .as_mut()
             .ok_or(format_err!("Lost socket"))?
+            .writer
             .send_message(&message)
             .map_err(|_| format_err!("Failed to send"))?;
         Ok(command.id)
     }
 
+    // Next message from chrome, None if nothing arrived before the timeout.
+    fn recv(&mut self, timeout: Option<Duration>) -> anyhow::Result<Option<serde_json::Value>> {
+        let messages = &self.ws.as_ref().ok_or(format_err!("Lost socket"))?.messages;
+        let message = match timeout {
+            None => messages.recv().map_err(|_| format_err!("Lost socket"))?,
+            Some(timeout) => match messages.recv_timeout(timeout) {
+                Ok(message) => message,
+                Err(mpsc::RecvTimeoutError::Timeout) => return Ok(None),
+                Err(mpsc::RecvTimeoutError::Disconnected) => {
+                    return Err(format_err!("Lost socket"))
+                }
+            },
+        };
+        match message {
+            OwnedMessage::Text(message) => Ok(Some(serde_json::from_str(&message)?)),
+            _ => Err(format_err!("Unexpected return message type")),
+        }
+    }
+
     fn get_result(
         &mut self,
         method: &str,
@@ -292,38 +474,109 @@ impl ChromeDriver {
         let id = self.send_command(method, params)?;
         loop {
             // If send_command was successful we should have a valid socket around.
-            match self
-                .ws
-                .as_mut()
-                .ok_or(format_err!("Lost socket"))?
-                .recv_message()?
-            {
-                websocket::OwnedMessage::Text(response) => {
-                    let mut response: serde_json::Value = serde_json::from_str(&response)?;
-                    if response["id"] != id {
-                        continue;
-                    }
-                    return Ok(response
-                        .get_mut("result")
-                        .ok_or(anyhow::format_err!("Missing result"))?
-                        .take());
-                }
-                _ => {
-                    return Err(format_err!("Unexpected return message type"));
-                }
+            let mut response = self.recv(None)?.ok_or(format_err!("Missing response"))?;
+            if response["id"] != id {
+                self.handle_event(&response)?;
+                continue;
+            }
+            if let Some(error) = response.get("error") {
+                return Err(format_err!("{} failed: {}", method, error["message"]));
+            }
+            return Ok(response
+                .get_mut("result")
+                .ok_or(anyhow::format_err!("Missing result"))?
+                .take());
+        }
+    }
+
+    // Waits while handling events, instead of sleeping.
+    fn wait(&mut self, duration: Duration) -> anyhow::Result<()> {
+        let deadline = Instant::now() + duration;
+        while let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+            if let Some(message) = self.recv(Some(remaining))? {
+                self.handle_event(&message)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Other messages are ignored, only paused requests need an answer.
+    fn handle_event(&mut self, message: &serde_json::Value) -> anyhow::Result<()> {
+        if message["method"] == "Page.loadEventFired" {
+            self.page_loads += 1;
+        }
+        if message["method"] != "Fetch.requestPaused" {
+            return Ok(());
+        }
+        let params = &message["params"];
+        let request_id = params["requestId"].as_str().unwrap_or_default();
+        let url = params["request"]["url"].as_str().unwrap_or_default();
+        let resource_type = params["resourceType"].as_str().unwrap_or_default();
+        let blocked = self.blocking.as_mut().is_some_and(|blocking| {
+            // Never block the page itself.
+            if resource_type == "Document" && params["frameId"] == *blocking.main_frame_id {
+                return false;
             }
+            let request = FilterRequest {
+                url,
+                resource_type,
+                page_host: &blocking.page_host,
+            };
+            let blocked = is_blocked(&blocking.lists, &request);
+            blocking.blocked += blocked as usize;
+            blocked
+        });
+        if blocked {
+            debug!("Blocked {} {}", resource_type, url);
+            let params = json!({"requestId": request_id, "errorReason": "BlockedByClient"});
+            self.send_command("Fetch.failRequest", params)?;
+        } else {
+            self.send_command("Fetch.continueRequest", json!({ "requestId": request_id }))?;
         }
+        Ok(())
+    }
+
+    // Blocks requests matching the filter lists for the given page, disabled if no lists.
+    pub fn set_blocking(
+        &mut self,
+        lists: Vec<Arc<FilterList>>,
+        page_url: &url::Url,
+    ) -> anyhow::Result<()> {
+        if lists.is_empty() {
+            self.blocking = None;
+            self.get_result("Fetch.disable", serde_json::Value::Null)?;
+            return Ok(());
+        }
+        let result = self.get_result("Page.getFrameTree", serde_json::Value::Null)?;
+        let main_frame_id = result["frameTree"]["frame"]["id"]
+            .as_str()
+            .ok_or_else(|| format_err!("Missing frame id"))?;
+        self.blocking = Some(Blocking {
+            lists,
+            page_host: page_url.host_str().unwrap_or_default().to_string(),
+            main_frame_id: main_frame_id.to_string(),
+            blocked: 0,
+        });
+        let params = json!({"patterns": [{"urlPattern": "*", "requestStage": "Request"}]});
+        self.get_result("Fetch.enable", params)?;
+        Ok(())
+    }
+
+    // Number of requests blocked since the last set_blocking.
+    pub fn blocked_requests(&self) -> usize {
+        self.blocking
+            .as_ref()
+            .map_or(0, |blocking| blocking.blocked)
     }
 
     pub fn navigate(&mut self, url: &str) -> anyhow::Result<()> {
         self.send_command("Page.navigate", json!({ "url": url }))?;
         // TODO: Proper wait.
-        std::thread::sleep(std::time::Duration::from_secs(5));
-        Ok(())
+        self.wait(Duration::from_secs(5))
     }
 
-    // TODO: Try to safeguard against too big pages with some hard limits.
-    pub fn save_screenshot(&mut self, dir: &std::path::Path) -> anyhow::Result<String> {
+    // Size of the whole page content in CSS pixels.
+    fn content_size(&mut self) -> anyhow::Result<(i64, i64)> {
         let result = self.get_result("Page.getLayoutMetrics", serde_json::Value::Null)?;
         let width = result["contentSize"]["width"]
             .as_i64()
@@ -331,37 +584,149 @@ impl ChromeDriver {
         let height = result["contentSize"]["height"]
             .as_i64()
             .ok_or_else(|| format_err!("Missing dimension"))?;
+        Ok((width, height))
+    }
+
+    // Emulates the device for this and following navigations, browser defaults if None.
+    pub fn emulate_device(&mut self, device: Option<Device>) -> anyhow::Result<()> {
+        self.device = device;
+        self.set_device_metrics()?;
+
+        let touch = self.device.as_ref().is_some_and(|d| d.touch);
+        let params = json!({"enabled": touch, "maxTouchPoints": 5});
+        self.get_result("Emulation.setTouchEmulationEnabled", params)?;
+
+        let user_agent = match self.device.as_ref().and_then(|d| d.user_agent.clone()) {
+            Some(user_agent) => user_agent,
+            None => self.default_user_agent()?,
+        };
+        let params = json!({ "userAgent": user_agent });
+        self.get_result("Emulation.setUserAgentOverride", params)?;
+        Ok(())
+    }
+
+    fn set_device_metrics(&mut self) -> anyhow::Result<()> {
+        let params = match self.device {
+            Some(ref device) => json!({"width": device.width, "screenWidth": device.width,
+                                       "height": device.height, "screenHeight": device.height,
+                                       "deviceScaleFactor": device.device_scale_factor,
+                                       "mobile": device.mobile}),
+            None => {
+                self.get_result(
+                    "Emulation.clearDeviceMetricsOverride",
+                    serde_json::Value::Null,
+                )?;
+                return Ok(());
+            }
+        };
+        self.get_result("Emulation.setDeviceMetricsOverride", params)?;
+        Ok(())
+    }
+
+    // There is no way to clear the user agent override, so remember the original.
+    fn default_user_agent(&mut self) -> anyhow::Result<String> {
+        if self.default_user_agent.is_none() {
+            let result = self.get_result("Browser.getVersion", serde_json::Value::Null)?;
+            let user_agent = result["userAgent"]
+                .as_str()
+                .ok_or_else(|| format_err!("Missing user agent"))?;
+            self.default_user_agent = Some(user_agent.to_string());
+        }
+        Ok(self.default_user_agent.clone().unwrap_or_default())
+    }
+
+    // Returns the image files, more than one if the page was split into tiles.
+    pub fn save_screenshot(
+        &mut self,
+        dir: &std::path::Path,
+        options: &ScreenshotOptions,
+    ) -> anyhow::Result<Vec<String>> {
+        let (width, height) = self.content_size()?;
+        let width = u32::try_from(width)?;
+        let mut height = u32::try_from(height)?;
+        let (scale_factor, mobile) = self
+            .device
+            .as_ref()
+            .map_or((1.0, false), |d| (d.device_scale_factor, d.mobile));
+        // The limits are in image pixels, high DPI devices render more of them per CSS pixel.
+        let to_css = |pixels: u32| ((pixels as f64 / scale_factor) as u32).max(1);
+        let max_height = to_css(options.max_height());
+        if height > max_height {
+            warn!("Page is {}px tall, capturing only {}px", height, max_height);
+            height = max_height;
+        }
+        let tile_height = to_css(options.tile_height()).min(height);
 
+        // Viewport of a single tile, parts below are rendered via captureBeyondViewport.
         let params = json!({"width": width, "screenWidth": width,
-                                "height": height, "screenHeight": height,
-                                "scale": 1, "deviceScaleFactor": 1,
-                                "mobile": false});
+                                "height": tile_height, "screenHeight": tile_height,
+                                "scale": 1, "deviceScaleFactor": scale_factor,
+                                "mobile": mobile});
         let _ = self.get_result("Emulation.setDeviceMetricsOverride", params)?;
 
-        let params =
-            json!({"clip": {"x": 0, "y": 0, "width": width, "height": height, "scale": 1}});
-        let result = self.get_result("Page.captureScreenshot", params)?;
-        let data = result["data"]
-            .as_str()
-            .ok_or_else(|| format_err!("Missing data"))?;
-        write_base64_to_directory(data, dir, ".png")
+        let result = self.capture_tiles(dir, options, width, height, tile_height);
+        // Go back to the emulated device, or clear the override to not leak
+        // it into the next request.
+        self.set_device_metrics()?;
+        result
+    }
+
+    fn capture_tiles(
+        &mut self,
+        dir: &std::path::Path,
+        options: &ScreenshotOptions,
+        width: u32,
+        height: u32,
+        tile_height: u32,
+    ) -> anyhow::Result<Vec<String>> {
+        let mut files = vec![];
+        let mut y = 0;
+        while y < height {
+            let params = options.to_params(width, y, tile_height.min(height - y));
+            let result = self.get_result("Page.captureScreenshot", params)?;
+            let data = result["data"]
+                .as_str()
+                .ok_or_else(|| format_err!("Missing data"))?;
+            files.push(write_base64_to_directory(
+                data,
+                dir,
+                options.format().extension(),
+            )?);
+            y += tile_height;
+        }
+        anyhow::ensure!(!files.is_empty(), "Empty page");
+        Ok(files)
     }
 
-    pub fn save_pdf(&mut self, dir: &std::path::Path) -> anyhow::Result<String> {
-        // A4 paper size in inches.
-        let params =
-            json!({"landscape": false, "scale": 1, "paperWidth": 8.27, "paperHeight": 11.69});
-        let result = self.get_result("Page.printToPDF", params)?;
+    pub fn save_pdf(
+        &mut self,
+        dir: &std::path::Path,
+        options: &PdfOptions,
+        url: &str,
+    ) -> anyhow::Result<String> {
+        let result = match options.mode.unwrap_or_default() {
+            PdfMode::Print => {
+                let params = options.to_params(url, chrono::Utc::now());
+                self.get_result("Page.printToPDF", params)?
+            }
+            PdfMode::Tall => {
+                // Many sites look nothing like the screen version when printed.
+                self.get_result("Emulation.setEmulatedMedia", json!({"media": "screen"}))?;
+                let result = self.content_size().and_then(|(width, height)| {
+                    let params = options.to_tall_params(width as f64, height as f64);
+                    self.get_result("Page.printToPDF", params)
+                });
+                self.get_result("Emulation.setEmulatedMedia", json!({"media": ""}))?;
+                result?
+            }
+        };
         let data = result["data"]
             .as_str()
             .ok_or_else(|| format_err!("Missing data"))?;
         write_base64_to_directory(data, dir, ".pdf")
     }
 
-    pub fn save_mhtml(
-        &mut self,
-        dir: &std::path::Path,
-    ) -> anyhow::Result<(String, Option<PageInfo>)> {
+    pub fn save_mhtml(&mut self, dir: &std::path::Path) -> anyhow::Result<Snapshot> {
         let result = self.get_result("Page.captureSnapshot", serde_json::Value::Null)?;
         let data = result["data"]
             .as_str()
@@ -369,18 +734,51 @@ impl ChromeDriver {
         write_mhtml_to_directory(data, dir)
     }
 
+    // Runs the script. Scripts returning a promise are done once it resolves,
+    // others get some time to take effect (e.g. submitted forms to navigate).
     pub fn run_script(&mut self, script: &str) -> anyhow::Result<()> {
-        let params = json!({"expression": script, "returnByValue": false});
-        let _result = self.get_result("Runtime.evaluate", params)?;
-        // TODO avoid sleep by handling the result somehow?
-        std::thread::sleep(std::time::Duration::from_secs(3));
+        if !self.execute(script)? {
+            self.wait(SCRIPT_SETTLE_TIME)?;
+        }
         Ok(())
     }
 
-    pub fn get_title(&mut self) -> anyhow::Result<String> {
-        let params = json!({"expression": "document.title", "returnByValue": true});
+    // Runs the script and awaits the returned promise. Returns whether there was one.
+    fn execute(&mut self, script: &str) -> anyhow::Result<bool> {
+        let params = json!({"expression": script, "returnByValue": false});
         let result = self.get_result("Runtime.evaluate", params)?;
-        let title = result["result"]["value"]
+        check_exception(&result)?;
+        if result["result"]["subtype"] != "promise" {
+            return Ok(false);
+        }
+        let params = json!({"promiseObjectId": result["result"]["objectId"]});
+        let result = self.get_result("Runtime.awaitPromise", params)?;
+        check_exception(&result)?;
+        Ok(true)
+    }
+
+    // Evaluates the expression (awaiting promises) and returns whether the result is truthy.
+    pub fn check_page(&mut self, expression: &str) -> anyhow::Result<bool> {
+        let result = self.evaluate(&format!("(async () => !!(await ({})))()", expression))?;
+        result
+            .as_bool()
+            .ok_or_else(|| format_err!("Invalid check result {}", result))
+    }
+
+    // Evaluates the expression and returns its value.
+    fn evaluate(&mut self, expression: &str) -> anyhow::Result<serde_json::Value> {
+        let params = json!({"expression": expression, "awaitPromise": true, "returnByValue": true});
+        let mut result = self.get_result("Runtime.evaluate", params)?;
+        check_exception(&result)?;
+        Ok(result["result"]
+            .get_mut("value")
+            .map(serde_json::Value::take)
+            .unwrap_or_default())
+    }
+
+    pub fn get_title(&mut self) -> anyhow::Result<String> {
+        let result = self.evaluate("document.title")?;
+        let title = result
             .as_str()
             .ok_or_else(|| format_err!("Failed to get title"))?;
         Ok(title.to_string())
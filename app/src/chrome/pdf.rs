@@ -0,0 +1,167 @@
+// Options for Page.printToPDF, set per domain and overridable per request.
+// All sizes are in inches like in the Chrome API.
+
+use serde::Deserialize;
+use serde_json::json;
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PaperName {
+    A4,
+    Letter,
+    Legal,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(untagged, deny_unknown_fields)]
+pub enum Paper {
+    // "a4", "letter" or "legal".
+    Named(PaperName),
+    // {width: 8.5, height: 14}
+    Custom { width: f64, height: f64 },
+}
+
+impl Paper {
+    fn size(&self) -> (f64, f64) {
+        match self {
+            Paper::Named(PaperName::A4) => (8.27, 11.69),
+            Paper::Named(PaperName::Letter) => (8.5, 11.0),
+            Paper::Named(PaperName::Legal) => (8.5, 14.0),
+            Paper::Custom { width, height } => (*width, *height),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PdfMode {
+    // Regular paged print output.
+    #[default]
+    Print,
+    // Single page as tall as the content, with screen media styles.
+    Tall,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Margins {
+    pub top: Option<f64>,
+    pub bottom: Option<f64>,
+    pub left: Option<f64>,
+    pub right: Option<f64>,
+}
+
+// Unset values fall back to the domain config, then to A4 portrait with
+// Chrome defaults for the rest.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PdfOptions {
+    pub mode: Option<PdfMode>,
+    // Paper, orientation, margins and templates are ignored in tall mode.
+    pub paper: Option<Paper>,
+    pub landscape: Option<bool>,
+    pub margins: Option<Margins>,
+    pub scale: Option<f64>,
+    pub print_background: Option<bool>,
+    // HTML templates, "{url}" and "{date}" are replaced with the captured URL
+    // and capture time. Chrome's own classes ("pageNumber", "totalPages", ...)
+    // work too. Setting either one enables header and footer.
+    pub header_template: Option<String>,
+    pub footer_template: Option<String>,
+    pub prefer_css_page_size: Option<bool>,
+}
+
+// CSS pixels per inch.
+const PIXELS_PER_INCH: f64 = 96.0;
+// PDF pages can't be larger than 200 inches, longer content is cut off.
+const MAX_PAGE_INCHES: f64 = 200.0;
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+impl PdfOptions {
+    // Values set in self take precedence over the fallback.
+    pub fn or(&self, fallback: &PdfOptions) -> PdfOptions {
+        PdfOptions {
+            mode: self.mode.or(fallback.mode),
+            paper: self.paper.or(fallback.paper),
+            landscape: self.landscape.or(fallback.landscape),
+            margins: self.margins.or(fallback.margins),
+            scale: self.scale.or(fallback.scale),
+            print_background: self.print_background.or(fallback.print_background),
+            header_template: self
+                .header_template
+                .clone()
+                .or_else(|| fallback.header_template.clone()),
+            footer_template: self
+                .footer_template
+                .clone()
+                .or_else(|| fallback.footer_template.clone()),
+            prefer_css_page_size: self.prefer_css_page_size.or(fallback.prefer_css_page_size),
+        }
+    }
+
+    // Params for the tall mode, given the content size in CSS pixels.
+    pub fn to_tall_params(&self, width: f64, height: f64) -> serde_json::Value {
+        let height = height / PIXELS_PER_INCH;
+        if height > MAX_PAGE_INCHES {
+            log::warn!("Page is too tall for PDF ({} inches), cutting off", height);
+        }
+        json!({
+            "paperWidth": width / PIXELS_PER_INCH,
+            "paperHeight": height.min(MAX_PAGE_INCHES),
+            "marginTop": 0,
+            "marginBottom": 0,
+            "marginLeft": 0,
+            "marginRight": 0,
+            "scale": 1,
+            "printBackground": self.print_background.unwrap_or(true),
+            // Rounding can push a few pixels onto a second page.
+            "pageRanges": "1",
+        })
+    }
+
+    pub fn to_params(&self, url: &str, date: chrono::DateTime<chrono::Utc>) -> serde_json::Value {
+        let (width, height) = self.paper.unwrap_or(Paper::Named(PaperName::A4)).size();
+        let mut params = json!({
+            "landscape": self.landscape.unwrap_or(false),
+            "scale": self.scale.unwrap_or(1.0),
+            "paperWidth": width,
+            "paperHeight": height,
+            "printBackground": self.print_background.unwrap_or(false),
+            "preferCSSPageSize": self.prefer_css_page_size.unwrap_or(false),
+        });
+        if let Some(margins) = self.margins {
+            for (name, value) in [
+                ("marginTop", margins.top),
+                ("marginBottom", margins.bottom),
+                ("marginLeft", margins.left),
+                ("marginRight", margins.right),
+            ] {
+                if let Some(value) = value {
+                    params[name] = json!(value);
+                }
+            }
+        }
+        if self.header_template.is_some() || self.footer_template.is_some() {
+            let date = date.format("%Y-%m-%d %H:%M:%S UTC").to_string();
+            let expand = |template: &Option<String>| {
+                // Empty template hides the default one.
+                template
+                    .as_deref()
+                    .map_or("<span></span>".to_string(), |t| {
+                        t.replace("{url}", &escape(url))
+                            .replace("{date}", &escape(&date))
+                    })
+            };
+            params["displayHeaderFooter"] = json!(true);
+            params["headerTemplate"] = json!(expand(&self.header_template));
+            params["footerTemplate"] = json!(expand(&self.footer_template));
+        }
+        params
+    }
+}
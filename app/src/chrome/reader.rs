@@ -0,0 +1,877 @@
+// Readability-style extraction of the main article content ("reader view").
+//
+// Loosely follows the classic Arc90 readability heuristics: paragraphs give
+// points to their parent and grandparent elements, class/id names hint at
+// content vs boilerplate, and link-heavy candidates are penalized. The best
+// scoring element is then cleaned up and rendered as plain HTML and Markdown.
+
+use markup5ever_rcdom as rcdom;
+use std::collections::HashMap;
+
+// Average adult silent reading speed.
+const WORDS_PER_MINUTE: usize = 230;
+
+// Pages with less text than this are not considered articles.
+const MIN_WORD_COUNT: usize = 50;
+
+#[derive(Debug)]
+pub struct Article {
+    pub html: String,
+    pub markdown: String,
+    pub text: String,
+    pub word_count: usize,
+}
+
+pub fn reading_time_minutes(word_count: usize) -> usize {
+    word_count.div_ceil(WORDS_PER_MINUTE).max(1)
+}
+
+// Simplified DOM of the extracted article, only containing whitelisted tags.
+#[derive(Debug)]
+enum Content {
+    Text(String),
+    Element {
+        tag: &'static str,
+        attrs: Vec<(&'static str, String)>,
+        children: Vec<Content>,
+    },
+}
+
+// Elements which never contain article content.
+const SKIPPED_TAGS: &[&str] = &[
+    "script", "style", "noscript", "template", "nav", "aside", "footer", "form", "iframe",
+    "button", "input", "select", "textarea", "svg", "canvas", "object", "embed", "dialog", "menu",
+    "link", "meta", "head",
+];
+
+// Elements kept in the output, everything else is unwrapped into its children.
+const KEPT_TAGS: &[&str] = &[
+    "p",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "ul",
+    "ol",
+    "li",
+    "blockquote",
+    "pre",
+    "code",
+    "a",
+    "em",
+    "i",
+    "strong",
+    "b",
+    "img",
+    "figure",
+    "figcaption",
+    "br",
+    "hr",
+    "div",
+];
+
+const BLOCK_TAGS: &[&str] = &[
+    "p",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "ul",
+    "ol",
+    "li",
+    "blockquote",
+    "pre",
+    "figure",
+    "figcaption",
+    "hr",
+    "div",
+];
+
+fn unlikely_regex() -> regex::Regex {
+    regex::Regex::new(
+        r"(?i)banner|breadcrumb|combx|comment|community|cookie|consent|disqus|extra|foot|header|legends|menu|related|remark|replies|rss|shoutbox|sidebar|skyscraper|social|sponsor|ad-break|agegate|pagination|pager|popup|newsletter|subscribe|share|promo",
+    )
+    .unwrap()
+}
+
+fn maybe_candidate_regex() -> regex::Regex {
+    regex::Regex::new(r"(?i)and|article|body|column|content|main|shadow").unwrap()
+}
+
+fn positive_regex() -> regex::Regex {
+    regex::Regex::new(
+        r"(?i)article|body|content|entry|hentry|h-entry|main|page|pagination|post|text|blog|story",
+    )
+    .unwrap()
+}
+
+fn negative_regex() -> regex::Regex {
+    regex::Regex::new(
+        r"(?i)hidden|^hid$| hid$| hid |^hid |banner|combx|comment|com-|contact|foot|footer|footnote|masthead|media|meta|outbrain|promo|related|scroll|share|shoutbox|sidebar|skyscraper|sponsor|shopping|tags|tool|widget|ad-|advert",
+    )
+    .unwrap()
+}
+
+struct Extractor {
+    unlikely: regex::Regex,
+    maybe_candidate: regex::Regex,
+    positive: regex::Regex,
+    negative: regex::Regex,
+    // Scores keyed by the node address, the handles are kept alive by the DOM.
+    scores: HashMap<*const rcdom::Node, (rcdom::Handle, f64)>,
+    // Fraction of the text of each element which is inside links.
+    link_densities: HashMap<*const rcdom::Node, f64>,
+}
+
+fn tag_name(handle: &rcdom::Handle) -> Option<String> {
+    match handle.data {
+        rcdom::NodeData::Element { ref name, .. } => Some(name.local.to_string()),
+        _ => None,
+    }
+}
+
+fn get_attribute(handle: &rcdom::Handle, name: &str) -> Option<String> {
+    match handle.data {
+        rcdom::NodeData::Element { ref attrs, .. } => attrs
+            .borrow()
+            .iter()
+            .find(|attr| attr.name.local.to_string() == name)
+            .map(|attr| attr.value.to_string()),
+        _ => None,
+    }
+}
+
+fn class_and_id(handle: &rcdom::Handle) -> String {
+    format!(
+        "{} {}",
+        get_attribute(handle, "class").unwrap_or_default(),
+        get_attribute(handle, "id").unwrap_or_default()
+    )
+}
+
+fn inner_text(handle: &rcdom::Handle, out: &mut String) {
+    match handle.data {
+        rcdom::NodeData::Text { ref contents } => out.push_str(&contents.borrow()),
+        rcdom::NodeData::Element { ref name, .. }
+            if SKIPPED_TAGS.contains(&name.local.as_ref()) => {}
+        _ => {
+            for child in handle.children.borrow().iter() {
+                inner_text(child, out);
+            }
+        }
+    }
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut last_space = false;
+    for c in text.chars() {
+        if c.is_whitespace() {
+            if !last_space {
+                result.push(' ');
+            }
+            last_space = true;
+        } else {
+            result.push(c);
+            last_space = false;
+        }
+    }
+    result
+}
+
+impl Extractor {
+    fn new() -> Extractor {
+        Extractor {
+            unlikely: unlikely_regex(),
+            maybe_candidate: maybe_candidate_regex(),
+            positive: positive_regex(),
+            negative: negative_regex(),
+            scores: HashMap::new(),
+            link_densities: HashMap::new(),
+        }
+    }
+
+    // Computes the link density of the element and everything below it in
+    // one pass, returns the text and link text lengths.
+    fn measure_links(&mut self, handle: &rcdom::Handle) -> (usize, usize) {
+        let (text, links) = match handle.data {
+            rcdom::NodeData::Text { ref contents } => {
+                let text = contents.borrow();
+                let length = text.split_whitespace().map(|w| w.chars().count() + 1);
+                return (length.sum(), 0);
+            }
+            rcdom::NodeData::Element { ref name, .. }
+                if SKIPPED_TAGS.contains(&name.local.as_ref()) =>
+            {
+                return (0, 0);
+            }
+            _ => handle
+                .children
+                .borrow()
+                .iter()
+                .map(|child| self.measure_links(child))
+                .fold((0, 0), |(text, links), (t, l)| (text + t, links + l)),
+        };
+        let links = if tag_name(handle).as_deref() == Some("a") {
+            text
+        } else {
+            links
+        };
+        if text > 0 {
+            self.link_densities
+                .insert(rcdom::Handle::as_ptr(handle), links as f64 / text as f64);
+        }
+        (text, links)
+    }
+
+    fn link_density(&self, handle: &rcdom::Handle) -> f64 {
+        self.link_densities
+            .get(&rcdom::Handle::as_ptr(handle))
+            .copied()
+            .unwrap_or(0.0)
+    }
+
+    fn class_weight(&self, handle: &rcdom::Handle) -> f64 {
+        let class_and_id = class_and_id(handle);
+        let mut weight = 0.0;
+        if self.negative.is_match(&class_and_id) {
+            weight -= 25.0;
+        }
+        if self.positive.is_match(&class_and_id) {
+            weight += 25.0;
+        }
+        weight
+    }
+
+    fn is_unlikely(&self, handle: &rcdom::Handle) -> bool {
+        let tag = match tag_name(handle) {
+            Some(tag) => tag,
+            None => return false,
+        };
+        if SKIPPED_TAGS.contains(&tag.as_str()) {
+            return true;
+        }
+        if tag == "body" || tag == "article" || tag == "main" || tag == "a" {
+            return false;
+        }
+        let class_and_id = class_and_id(handle);
+        self.unlikely.is_match(&class_and_id) && !self.maybe_candidate.is_match(&class_and_id)
+    }
+
+    fn initial_score(&self, handle: &rcdom::Handle) -> f64 {
+        let tag_score = match tag_name(handle).as_deref() {
+            Some("article") => 10.0,
+            Some("div") | Some("main") | Some("section") => 5.0,
+            Some("pre") | Some("td") | Some("blockquote") => 3.0,
+            Some("ol") | Some("ul") | Some("dl") | Some("form") | Some("li") => -3.0,
+            Some("h1") | Some("h2") | Some("h3") | Some("h4") | Some("h5") | Some("h6")
+            | Some("th") => -5.0,
+            _ => 0.0,
+        };
+        tag_score + self.class_weight(handle)
+    }
+
+    fn add_score(&mut self, handle: &rcdom::Handle, score: f64) {
+        let initial = self.initial_score(handle);
+        let entry = self
+            .scores
+            .entry(rcdom::Handle::as_ptr(handle))
+            .or_insert_with(|| (handle.clone(), initial));
+        entry.1 += score;
+    }
+
+    fn score_paragraphs(&mut self, handle: &rcdom::Handle, ancestors: &mut Vec<rcdom::Handle>) {
+        if self.is_unlikely(handle) {
+            return;
+        }
+        if let Some(tag) = tag_name(handle) {
+            if tag == "p" || tag == "pre" || tag == "td" {
+                let mut text = String::new();
+                inner_text(handle, &mut text);
+                let text = collapse_whitespace(&text);
+                let length = text.trim().chars().count();
+                if length >= 25 {
+                    let score =
+                        1.0 + text.matches(',').count() as f64 + (length / 100).min(3) as f64;
+                    // Parent gets the full score, grandparent half of it.
+                    let mut parents = ancestors.iter().rev();
+                    if let Some(parent) = parents.next() {
+                        self.add_score(&parent.clone(), score);
+                    }
+                    if let Some(grandparent) = parents.next() {
+                        self.add_score(&grandparent.clone(), score / 2.0);
+                    }
+                }
+                return;
+            }
+        }
+        ancestors.push(handle.clone());
+        for child in handle.children.borrow().iter() {
+            self.score_paragraphs(child, ancestors);
+        }
+        ancestors.pop();
+    }
+
+    fn best_candidate(&self) -> Option<rcdom::Handle> {
+        self.scores
+            .values()
+            .map(|(handle, score)| (handle, score * (1.0 - self.link_density(handle))))
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(handle, _)| handle.clone())
+    }
+
+    // Converts the DOM subtree into simplified content, dropping boilerplate.
+    fn clean(&self, handle: &rcdom::Handle, out: &mut Vec<Content>) {
+        match handle.data {
+            rcdom::NodeData::Text { ref contents } => {
+                out.push(Content::Text(contents.borrow().to_string()));
+            }
+            rcdom::NodeData::Element { ref name, .. } => {
+                if self.is_unlikely(handle) {
+                    return;
+                }
+                let tag = name.local.to_string();
+                // Link-heavy blocks with negative hints are most likely menus.
+                if matches!(tag.as_str(), "div" | "section" | "ul" | "ol" | "table")
+                    && self.class_weight(handle) < 0.0
+                    && self.link_density(handle) > 0.33
+                {
+                    return;
+                }
+
+                let mut children = Vec::new();
+                for child in handle.children.borrow().iter() {
+                    self.clean(child, &mut children);
+                }
+
+                // Unwrap unknown elements, but keep block containers separated.
+                let tag = match tag.as_str() {
+                    "section" | "article" | "main" | "table" | "tr" | "dl" | "dd" => "div",
+                    tag => match KEPT_TAGS.iter().find(|t| **t == tag) {
+                        Some(tag) => *tag,
+                        None => {
+                            out.extend(children);
+                            return;
+                        }
+                    },
+                };
+                let attrs: Vec<(&'static str, String)> = match tag {
+                    "a" => get_attribute(handle, "href")
+                        .filter(|href| is_safe_url(href))
+                        .map(|href| vec![("href", href)])
+                        .unwrap_or_default(),
+                    "img" => match get_attribute(handle, "src").filter(|src| is_safe_url(src)) {
+                        Some(src) => vec![
+                            ("src", src),
+                            ("alt", get_attribute(handle, "alt").unwrap_or_default()),
+                        ],
+                        // Images without source are lazy-load placeholders.
+                        None => return,
+                    },
+                    _ => vec![],
+                };
+                out.push(Content::Element {
+                    tag,
+                    attrs,
+                    children,
+                });
+            }
+            _ => {}
+        }
+    }
+}
+
+// The article is served from the same origin as the archive and the API, so
+// only http(s) and mailto links and relative URLs are kept, no "javascript:".
+fn is_safe_url(url: &str) -> bool {
+    // Browsers ignore whitespace and control characters in the scheme.
+    let url: String = url
+        .chars()
+        .filter(|c| !c.is_ascii_whitespace() && !c.is_ascii_control())
+        .collect();
+    match url.find([':', '/', '?', '#']) {
+        Some(i) if url[i..].starts_with(':') => {
+            matches!(
+                url[..i].to_ascii_lowercase().as_str(),
+                "http" | "https" | "mailto"
+            )
+        }
+        _ => true,
+    }
+}
+
+fn find_element(handle: &rcdom::Handle, tag: &str) -> Option<rcdom::Handle> {
+    if tag_name(handle).as_deref() == Some(tag) {
+        return Some(handle.clone());
+    }
+    handle
+        .children
+        .borrow()
+        .iter()
+        .find_map(|child| find_element(child, tag))
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn is_empty(content: &[Content]) -> bool {
+    content.iter().all(|c| match c {
+        Content::Text(text) => text.trim().is_empty(),
+        Content::Element { tag, children, .. } => {
+            !matches!(*tag, "img" | "br" | "hr") && is_empty(children)
+        }
+    })
+}
+
+fn to_html(content: &[Content], out: &mut String) {
+    for c in content {
+        match c {
+            Content::Text(text) => out.push_str(&escape_html(text)),
+            Content::Element {
+                tag,
+                attrs,
+                children,
+            } => {
+                if BLOCK_TAGS.contains(tag) && *tag != "hr" && is_empty(children) {
+                    continue;
+                }
+                out.push('<');
+                out.push_str(tag);
+                for (name, value) in attrs {
+                    out.push_str(&format!(" {}=\"{}\"", name, escape_html(value)));
+                }
+                out.push('>');
+                if matches!(*tag, "img" | "br" | "hr") {
+                    continue;
+                }
+                to_html(children, out);
+                out.push_str(&format!("</{}>", tag));
+                if BLOCK_TAGS.contains(tag) {
+                    out.push('\n');
+                }
+            }
+        }
+    }
+}
+
+fn to_text(content: &[Content], out: &mut String) {
+    for c in content {
+        match c {
+            Content::Text(text) => out.push_str(text),
+            Content::Element { tag, children, .. } => {
+                if BLOCK_TAGS.contains(tag) && !out.is_empty() && !out.ends_with('\n') {
+                    out.push('\n');
+                }
+                to_text(children, out);
+                if BLOCK_TAGS.contains(tag) || *tag == "br" {
+                    out.push('\n');
+                }
+            }
+        }
+    }
+}
+
+// Escapes the characters with a meaning in Markdown, in text from the page.
+fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(
+            c,
+            '\\' | '`' | '*' | '_' | '[' | ']' | '(' | ')' | '#' | '<' | '>'
+        ) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+// Spaces and parentheses would end the link target.
+fn markdown_url(url: &str) -> String {
+    url.replace(' ', "%20")
+        .replace('(', "%28")
+        .replace(')', "%29")
+}
+
+// Markdown rendering. Nested blocks (quotes, list items) are rendered
+// separately and then indented or prefixed line by line.
+struct MarkdownWriter {
+    out: String,
+}
+
+impl MarkdownWriter {
+    fn render(content: &[Content]) -> String {
+        let mut writer = MarkdownWriter { out: String::new() };
+        writer.write(content);
+        writer.out.trim().to_string()
+    }
+
+    fn block_break(&mut self) {
+        self.out.truncate(self.out.trim_end().len());
+        if !self.out.is_empty() {
+            self.out.push_str("\n\n");
+        }
+    }
+
+    fn block(&mut self, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        self.block_break();
+        self.out.push_str(text);
+        self.block_break();
+    }
+
+    fn inline(&mut self, text: &str) {
+        let text = collapse_whitespace(text);
+        // Avoid leading and repeated spaces.
+        if self.out.is_empty() || self.out.ends_with([' ', '\n']) {
+            self.out.push_str(text.trim_start());
+        } else {
+            self.out.push_str(&text);
+        }
+    }
+
+    fn write(&mut self, content: &[Content]) {
+        for c in content {
+            let (tag, attrs, children) = match c {
+                Content::Text(text) => {
+                    self.inline(&escape_markdown(text));
+                    continue;
+                }
+                Content::Element {
+                    tag,
+                    attrs,
+                    children,
+                } => (*tag, attrs, children),
+            };
+            let attr = |name: &str| {
+                attrs
+                    .iter()
+                    .find(|(n, _)| *n == name)
+                    .map(|(_, v)| v.as_str())
+                    .unwrap_or_default()
+            };
+            match tag {
+                "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                    let text = Self::render(children).replace('\n', " ");
+                    if !text.is_empty() {
+                        let level = tag[1..].parse::<usize>().unwrap_or(1);
+                        self.block(&format!("{} {}", "#".repeat(level), text));
+                    }
+                }
+                "blockquote" => {
+                    let text = Self::render(children);
+                    let quoted: Vec<_> = text
+                        .lines()
+                        .map(|line| format!("> {}", line).trim_end().to_string())
+                        .collect();
+                    self.block(&quoted.join("\n"));
+                }
+                "ul" | "ol" => {
+                    let mut items = Vec::new();
+                    for child in children {
+                        let text = match child {
+                            Content::Element {
+                                tag: "li",
+                                children,
+                                ..
+                            } => Self::render(children),
+                            other => Self::render(std::slice::from_ref(other)),
+                        };
+                        if text.is_empty() {
+                            continue;
+                        }
+                        let marker = if tag == "ol" {
+                            format!("{}. ", items.len() + 1)
+                        } else {
+                            "- ".to_string()
+                        };
+                        let indent = " ".repeat(marker.len());
+                        let item: Vec<_> = text
+                            .lines()
+                            .enumerate()
+                            .map(|(i, line)| match (i, line.is_empty()) {
+                                (0, _) => format!("{}{}", marker, line),
+                                (_, true) => String::new(),
+                                _ => format!("{}{}", indent, line),
+                            })
+                            .collect();
+                        items.push(item.join("\n"));
+                    }
+                    self.block(&items.join("\n"));
+                }
+                "pre" => {
+                    let mut text = String::new();
+                    to_text(children, &mut text);
+                    self.block(&format!("```\n{}\n```", text.trim_matches('\n')));
+                }
+                "code" => {
+                    let mut text = String::new();
+                    to_text(children, &mut text);
+                    self.inline(&format!("`{}`", collapse_whitespace(text.trim())));
+                }
+                "em" | "i" | "strong" | "b" => {
+                    let text = Self::render(children).replace('\n', " ");
+                    if !text.is_empty() {
+                        let marker = if tag == "em" || tag == "i" { "_" } else { "**" };
+                        self.inline(&format!(" {}{}{}", marker, text, marker));
+                    }
+                }
+                "a" => {
+                    let text = Self::render(children).replace('\n', " ");
+                    let href = attr("href");
+                    if href.is_empty() {
+                        self.inline(&text);
+                    } else if !text.is_empty() {
+                        self.inline(&format!(" [{}]({})", text, markdown_url(href)));
+                    }
+                }
+                "img" => {
+                    let alt = escape_markdown(attr("alt").trim());
+                    self.inline(&format!(" ![{}]({})", alt, markdown_url(attr("src"))));
+                }
+                "br" => {
+                    self.out.truncate(self.out.trim_end_matches(' ').len());
+                    self.out.push('\n');
+                }
+                "hr" => self.block("---"),
+                _ if BLOCK_TAGS.contains(&tag) => {
+                    let text = Self::render(children);
+                    self.block(&text);
+                }
+                _ => self.write(children),
+            }
+        }
+    }
+}
+
+fn to_markdown(title: Option<&str>, content: &[Content]) -> String {
+    let mut markdown = String::new();
+    if let Some(title) = title {
+        markdown.push_str(&format!("# {}\n\n", escape_markdown(title)));
+    }
+    markdown.push_str(&MarkdownWriter::render(content));
+    markdown + "\n"
+}
+
+fn html_document(title: Option<&str>, body: &str) -> String {
+    let title = escape_html(title.unwrap_or("Article"));
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<meta name="referrer" content="no-referrer">
+<title>{title}</title>
+<style>
+body {{ max-width: 40em; margin: 2em auto; padding: 0 1em; font: 18px/1.6 Georgia, serif; color: #222; }}
+img {{ max-width: 100%; height: auto; }}
+pre {{ overflow-x: auto; background: #f4f4f4; padding: 1em; }}
+blockquote {{ border-left: 3px solid #ccc; margin-left: 0; padding-left: 1em; color: #555; }}
+</style>
+</head>
+<body>
+<h1>{title}</h1>
+{body}</body>
+</html>
+"#
+    )
+}
+
+// Text of the whole page, for when there is no article to extract.
+pub fn page_text(html: &str) -> anyhow::Result<String> {
+    use html5ever::tendril::TendrilSink;
+
+    let mut reader = std::io::Cursor::new(html);
+    let document = html5ever::parse_document(rcdom::RcDom::default(), Default::default())
+        .from_utf8()
+        .read_from(&mut reader)?;
+    let body = find_element(&document.document, "body")
+        .ok_or_else(|| anyhow::format_err!("Missing body"))?;
+    let mut text = String::new();
+    inner_text(&body, &mut text);
+    Ok(collapse_whitespace(&text).trim().to_string())
+}
+
+impl Article {
+    pub fn from_html(html: &str) -> anyhow::Result<Article> {
+        use html5ever::tendril::TendrilSink;
+
+        let mut reader = std::io::Cursor::new(html);
+        let document = html5ever::parse_document(rcdom::RcDom::default(), Default::default())
+            .from_utf8()
+            .read_from(&mut reader)?;
+
+        let title = find_element(&document.document, "title")
+            .map(|title| {
+                let mut text = String::new();
+                inner_text(&title, &mut text);
+                collapse_whitespace(&text).trim().to_string()
+            })
+            .filter(|title| !title.is_empty());
+
+        let body = find_element(&document.document, "body")
+            .ok_or_else(|| anyhow::format_err!("Missing body"))?;
+
+        let mut extractor = Extractor::new();
+        extractor.measure_links(&body);
+        extractor.score_paragraphs(&body, &mut vec![]);
+        let candidate = extractor.best_candidate().unwrap_or(body);
+
+        let mut content = Vec::new();
+        for child in candidate.children.borrow().iter() {
+            extractor.clean(child, &mut content);
+        }
+
+        let mut text = String::new();
+        to_text(&content, &mut text);
+        let text = text
+            .lines()
+            .map(|line| collapse_whitespace(line).trim().to_string())
+            .filter(|line| !line.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n");
+        let word_count = text.split_whitespace().count();
+        anyhow::ensure!(
+            word_count >= MIN_WORD_COUNT,
+            "Too little text for an article ({} words)",
+            word_count
+        );
+
+        let mut body_html = String::new();
+        to_html(&content, &mut body_html);
+
+        Ok(Article {
+            html: html_document(title.as_deref(), &body_html),
+            markdown: to_markdown(title.as_deref(), &content),
+            text,
+            word_count,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PARAGRAPH: &str = "The council approved the new budget on Tuesday, after a long debate \
+        about the cost of repairing the old bridge, the schools and the public library.";
+
+    fn page(article: &str) -> String {
+        format!(
+            r#"<html><head><title>Budget approved</title><script>var x = 1;</script></head><body>
+            <nav><a href="/">Home</a> <a href="/news">News</a> <a href="/sport">Sport</a></nav>
+            <div class="sidebar"><p>Most read: celebrity gossip and other stories.</p></div>
+            <article class="post-content">{}</article>
+            <div id="comments"><p>First! Great article, totally agree with everything.</p></div>
+            <footer>Copyright Example News</footer>
+            </body></html>"#,
+            article
+        )
+    }
+
+    #[test]
+    fn removes_boilerplate() {
+        let article = format!(
+            "<h2>Details</h2><p>{p}</p><p>{p} <em>Really.</em></p><p>{p}</p>",
+            p = PARAGRAPH
+        );
+        let article = Article::from_html(&page(&article)).unwrap();
+        for boilerplate in ["Home", "Most read", "First!", "Copyright", "var x"] {
+            assert!(!article.html.contains(boilerplate), "{}", boilerplate);
+            assert!(!article.text.contains(boilerplate), "{}", boilerplate);
+        }
+        assert!(article.html.contains("<title>Budget approved</title>"));
+        assert!(article.html.contains("<h2>Details</h2>"));
+        assert!(article.text.starts_with("Details\nThe council approved"));
+        assert_eq!(article.word_count, 1 + 3 * 26 + 1);
+    }
+
+    #[test]
+    fn markdown() {
+        let article = format!(
+            r#"<p>{p} See <a href="https://example.com/report">the report</a> and
+            <strong>the minutes</strong>.</p>
+            <ul><li>First item</li><li>Second item</li></ul>
+            <p>{p}<br>Next line</p><img src="/chart.png" alt="Chart">"#,
+            p = PARAGRAPH
+        );
+        let markdown = Article::from_html(&page(&article)).unwrap().markdown;
+        assert!(
+            markdown.starts_with("# Budget approved\n\n"),
+            "{}",
+            markdown
+        );
+        assert!(markdown.contains(" [the report](https://example.com/report)"));
+        assert!(markdown.contains(" **the minutes**"));
+        assert!(
+            markdown.contains("- First item\n- Second item"),
+            "{}",
+            markdown
+        );
+        assert!(
+            markdown.contains("public library.\nNext line"),
+            "{}",
+            markdown
+        );
+        assert!(markdown.contains("![Chart](/chart.png)"));
+
+        let article = format!(
+            r#"<p>{p} Notes [1] *not bold* (<a href="/wiki/A_(b)">see [2]</a>)</p>
+            <p>{p}<img src="/a b.png" alt="Chart] (x)"> <code>a_[b]</code></p>"#,
+            p = PARAGRAPH
+        );
+        let markdown = Article::from_html(&page(&article)).unwrap().markdown;
+        assert!(
+            markdown.contains(r"Notes \[1\] \*not bold\* \( [see \[2\]](/wiki/A_%28b%29)\)"),
+            "{}",
+            markdown
+        );
+        assert!(
+            markdown.contains(r"![Chart\] \(x\)](/a%20b.png) `a_[b]`"),
+            "{}",
+            markdown
+        );
+    }
+
+    #[test]
+    fn unsafe_urls() {
+        let article = format!(
+            r#"<p>{p} <a href="javascript:alert(1)">one</a> <a href=" JaVa&#9;Script:alert(2)">two</a>
+            <a href="data:text/html,x">three</a> <a href="/relative">four</a>
+            <a href="mailto:news@example.com">five</a></p>
+            <p>{p}<img src="javascript:alert(3)" alt="Bad"><img src="https://example.com/a.png" alt="Good"></p>"#,
+            p = PARAGRAPH
+        );
+        let article = Article::from_html(&page(&article)).unwrap();
+        for html in [&article.html, &article.markdown] {
+            assert!(!html.to_lowercase().contains("script:"), "{}", html);
+            assert!(!html.contains("data:"), "{}", html);
+            assert!(!html.contains("Bad"), "{}", html);
+        }
+        assert!(article.html.contains("<a>one</a>"));
+        assert!(article.html.contains(r#"<a href="/relative">four</a>"#));
+        assert!(article
+            .html
+            .contains(r#"<a href="mailto:news@example.com">five</a>"#));
+        assert!(article
+            .html
+            .contains(r#"<img src="https://example.com/a.png" alt="Good">"#));
+
+        assert!(is_safe_url("https://example.com/a:b"));
+        assert!(is_safe_url("//example.com/x"));
+        assert!(is_safe_url("page?next=javascript:x"));
+        assert!(!is_safe_url("vbscript:x"));
+        assert!(!is_safe_url("\u{1}javascript:x"));
+    }
+}
@@ -0,0 +1,127 @@
+// Options for Page.captureScreenshot, set per domain and overridable per request.
+
+use serde::Deserialize;
+use serde_json::json;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageFormat {
+    #[default]
+    Png,
+    Jpeg,
+    Webp,
+}
+
+impl ImageFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ImageFormat::Png => ".png",
+            ImageFormat::Jpeg => ".jpg",
+            ImageFormat::Webp => ".webp",
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            ImageFormat::Png => "png",
+            ImageFormat::Jpeg => "jpeg",
+            ImageFormat::Webp => "webp",
+        }
+    }
+}
+
+// Infinite scroll pages can be arbitrarily long, Chrome crashes when asked to
+// capture them in one go. Sizes are in image pixels, i.e. CSS pixels times the
+// device scale factor, tiles must stay below Chrome's 16384px texture limit.
+const MAX_TEXTURE_HEIGHT: u32 = 16384;
+const DEFAULT_MAX_HEIGHT: u32 = 32768;
+const DEFAULT_TILE_HEIGHT: u32 = 8192;
+const DEFAULT_QUALITY: u8 = 80;
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScreenshotOptions {
+    pub format: Option<ImageFormat>,
+    // JPEG and WebP only, 0-100.
+    pub quality: Option<u8>,
+    // Content below this is cut off.
+    pub max_height: Option<u32>,
+    // Taller pages are split into multiple images of this height.
+    #[serde(default, deserialize_with = "deserialize_tile_height")]
+    pub tile_height: Option<u32>,
+}
+
+fn deserialize_tile_height<'de, D>(deserializer: D) -> Result<Option<u32>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let height: Option<u32> = Deserialize::deserialize(deserializer)?;
+    match height {
+        Some(height) if height > MAX_TEXTURE_HEIGHT => Err(serde::de::Error::custom(format!(
+            "{} is above Chrome's limit of {}",
+            height, MAX_TEXTURE_HEIGHT
+        ))),
+        _ => Ok(height),
+    }
+}
+
+impl ScreenshotOptions {
+    // Values set in self take precedence over the fallback.
+    pub fn or(&self, fallback: &ScreenshotOptions) -> ScreenshotOptions {
+        ScreenshotOptions {
+            format: self.format.or(fallback.format),
+            quality: self.quality.or(fallback.quality),
+            max_height: self.max_height.or(fallback.max_height),
+            tile_height: self.tile_height.or(fallback.tile_height),
+        }
+    }
+
+    pub fn format(&self) -> ImageFormat {
+        self.format.unwrap_or_default()
+    }
+
+    pub fn max_height(&self) -> u32 {
+        self.max_height.unwrap_or(DEFAULT_MAX_HEIGHT)
+    }
+
+    pub fn tile_height(&self) -> u32 {
+        // Zero would never finish, taller tiles come out blank.
+        self.tile_height
+            .unwrap_or(DEFAULT_TILE_HEIGHT)
+            .clamp(1, MAX_TEXTURE_HEIGHT)
+    }
+
+    // Params capturing the given part of the page.
+    pub fn to_params(&self, width: u32, y: u32, height: u32) -> serde_json::Value {
+        let mut params = json!({
+            "format": self.format().name(),
+            "clip": {"x": 0, "y": y, "width": width, "height": height, "scale": 1},
+            "captureBeyondViewport": true,
+        });
+        if self.format() != ImageFormat::Png {
+            params["quality"] = json!(self.quality.unwrap_or(DEFAULT_QUALITY).min(100));
+        }
+        params
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tile_height_limit() {
+        let options: ScreenshotOptions = serde_yaml::from_str("tile_height: 16384").unwrap();
+        assert_eq!(options.tile_height(), 16384);
+        let error = serde_yaml::from_str::<ScreenshotOptions>("tile_height: 20000").unwrap_err();
+        assert!(
+            error.to_string().contains("above Chrome's limit"),
+            "{}",
+            error
+        );
+        let options: ScreenshotOptions = serde_yaml::from_str("tile_height: 0").unwrap();
+        assert_eq!(options.tile_height(), 1);
+        let options: ScreenshotOptions = serde_yaml::from_str("{}").unwrap();
+        assert_eq!(options.tile_height(), DEFAULT_TILE_HEIGHT);
+    }
+}
@@ -0,0 +1,101 @@
+// Scrolling through the page before capture, so that lazy loaded images and
+// infinite scroll content show up in the screenshot.
+
+use super::ChromeDriver;
+
+use std::time::{Duration, Instant};
+
+use anyhow::format_err;
+use log::info;
+use serde::Deserialize;
+
+fn default_delay_ms() -> u64 {
+    250
+}
+
+fn default_max_height() -> u64 {
+    20000
+}
+
+fn default_idle_timeout_ms() -> u64 {
+    5000
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScrollOptions {
+    // Pixels per step, viewport height if unset.
+    pub step: Option<u64>,
+    // Delay after each step.
+    #[serde(default = "default_delay_ms")]
+    pub delay_ms: u64,
+    // Stops scrolling at this height even if there is more content.
+    #[serde(default = "default_max_height")]
+    pub max_height: u64,
+    // Maximum time to wait for the network to settle after scrolling.
+    #[serde(default = "default_idle_timeout_ms")]
+    pub idle_timeout_ms: u64,
+}
+
+// The network counts as idle if no resource was loaded for this long.
+const IDLE_TIME: Duration = Duration::from_millis(500);
+const IDLE_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+impl ChromeDriver {
+    // Scrolls to the bottom, waits for the network to settle and goes back to the top.
+    pub fn scroll_to_bottom(&mut self, options: &ScrollOptions) -> anyhow::Result<()> {
+        let step = match options.step {
+            Some(step) => step.to_string(),
+            None => "window.innerHeight".to_string(),
+        };
+        let script = format!(
+            "window.scrollBy(0, {}); [window.scrollY + window.innerHeight, document.documentElement.scrollHeight]",
+            step
+        );
+        // Default buffer only holds 250 entries, the idle check needs all of them.
+        self.evaluate("performance.setResourceTimingBufferSize(100000)")?;
+        let mut steps = 0;
+        let mut last_position = None;
+        loop {
+            let result = self.evaluate(&script)?;
+            let position = result[0]
+                .as_f64()
+                .ok_or_else(|| format_err!("Invalid scroll position"))?;
+            let height = result[1]
+                .as_f64()
+                .ok_or_else(|| format_err!("Invalid scroll height"))?;
+            steps += 1;
+            self.wait(Duration::from_millis(options.delay_ms))?;
+            // Pages scrolling inside an element don't move the window at all.
+            let stuck = last_position == Some(position);
+            last_position = Some(position);
+            if stuck || position >= height || position >= options.max_height as f64 {
+                info!("Scrolled {} steps to {}px of {}px", steps, position, height);
+                break;
+            }
+        }
+
+        self.wait_for_network_idle(Duration::from_millis(options.idle_timeout_ms))?;
+        self.evaluate("window.scrollTo(0, 0)")?;
+        Ok(())
+    }
+
+    // Polls the number of loaded resources until it stops changing.
+    fn wait_for_network_idle(&mut self, timeout: Duration) -> anyhow::Result<()> {
+        let start = Instant::now();
+        let mut last_count = None;
+        let mut last_change = Instant::now();
+        while start.elapsed() < timeout {
+            let count = self.evaluate("performance.getEntriesByType('resource').length")?;
+            if last_count.as_ref() != Some(&count) {
+                last_count = Some(count);
+                last_change = Instant::now();
+            } else if last_change.elapsed() >= IDLE_TIME {
+                return Ok(());
+            }
+            self.wait(IDLE_POLL_INTERVAL)?;
+        }
+        info!("Network still busy after {:?}, capturing anyway", timeout);
+        Ok(())
+    }
+}
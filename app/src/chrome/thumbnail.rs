@@ -0,0 +1,39 @@
+// Small preview of the captured page, cropped from the top of the screenshot.
+
+use super::{bytes_to_hash, write_bytes_to_directory};
+
+use image::codecs::jpeg::JpegEncoder;
+
+const THUMBNAIL_WIDTH: u32 = 480;
+const THUMBNAIL_QUALITY: u8 = 85;
+// Aspect ratio (height / width) of the crop without an emulated device,
+// roughly what is visible above the fold on a desktop.
+const DEFAULT_VIEWPORT_RATIO: f64 = 10.0 / 16.0;
+
+// Creates the thumbnail from the (first) screenshot file in the output
+// directory, cropped to the viewport (width, height) of the emulated device.
+pub fn save_thumbnail(
+    dir: &std::path::Path,
+    screenshot_file: &str,
+    viewport: Option<(u32, u32)>,
+) -> anyhow::Result<String> {
+    let ratio = match viewport {
+        Some((width, height)) if width > 0 => height as f64 / width as f64,
+        _ => DEFAULT_VIEWPORT_RATIO,
+    };
+    let screenshot = image::open(dir.join(screenshot_file))?;
+    let width = screenshot.width();
+    let height = screenshot.height().min((width as f64 * ratio) as u32);
+    anyhow::ensure!(width > 0 && height > 0, "Empty screenshot");
+
+    let thumbnail = screenshot
+        .crop_imm(0, 0, width, height)
+        .thumbnail(THUMBNAIL_WIDTH, THUMBNAIL_WIDTH)
+        // JPEG has no alpha channel.
+        .into_rgb8();
+    let mut bytes = vec![];
+    thumbnail.write_with_encoder(JpegEncoder::new_with_quality(&mut bytes, THUMBNAIL_QUALITY))?;
+    let filename = bytes_to_hash(&bytes) + ".thumb.jpg";
+    write_bytes_to_directory(&bytes, dir, &filename)?;
+    Ok(filename)
+}
@@ -0,0 +1,316 @@
+// Command line tools next to the server, sharing its configuration from the environment:
+//
+//   app check-config        validates the environment and domains.yaml
+//   app test-domain <url>   shows which domain config matches the URL and what the render does
+//   app render <url>        renders the URL into a local directory, without the server
+//   app import <source>     archives all URLs of a list, sitemap or feed through the server API
+
+use crate::bulk;
+use crate::chrome::{ChromeDriver, PageInfo};
+use crate::config::Config;
+use crate::renderer::{
+    handle_request_once, new_request_id, render_plan, Format, RenderOptions, RenderRequest,
+};
+
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+pub const USAGE: &str = "Usage:
+  app                      run the server
+  app check-config         validate the environment and domain configs
+  app test-domain <url>    show the domain config matching the URL and the render steps
+  app render <url> [--out <dir>] [--formats pdf,png,mhtml]
+                           render the URL into the directory (default: current), with a manifest.json
+  app import <file-or-url> [--server <url>] [--delay <seconds>]
+                           archive all URLs of a list, sitemap or RSS/Atom feed via the running server";
+
+// Loading the config validates everything: unknown fields, regexes, and
+// referenced files, secrets and filter lists.
+pub fn check_config() -> anyhow::Result<()> {
+    let config = Config::from_env()?;
+    let domains = config.domains.get();
+    println!(
+        "Config is valid, {} domains in {}:",
+        domains.len(),
+        config.domain_config_path.display()
+    );
+    for domain in domains.iter() {
+        let host = domain.host.as_ref().map_or("*", |h| h.as_str());
+        println!(
+            "  {} (host {}, priority {})",
+            domain.name, host, domain.priority
+        );
+    }
+    Ok(())
+}
+
+pub fn test_domain(url: &str) -> anyhow::Result<()> {
+    let config = Config::from_env()?;
+    let url = url::Url::parse(url)?;
+    let domains = config.domains.get();
+    let mut matching = domains.iter().filter(|d| d.matches(&url));
+    let domain = matching
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("No domain config matches {}", url))?;
+    println!("{} matches {}", url, domain.name);
+    for other in matching {
+        println!(
+            "  also matches {} (not used, lower priority or later in the file)",
+            other.name
+        );
+    }
+    println!("Render steps:");
+    for (i, step) in render_plan(domain, &RenderOptions::default())
+        .iter()
+        .enumerate()
+    {
+        println!("  {}. {}", i + 1, step);
+    }
+    Ok(())
+}
+
+// Written next to the rendered files, all paths are relative to it.
+#[derive(Serialize)]
+struct Manifest {
+    url: String,
+    title: String,
+    rendered_at: chrono::DateTime<chrono::Utc>,
+    domain_config: String,
+    pdf: Option<String>,
+    screenshots: Vec<String>,
+    thumbnail: Option<String>,
+    mhtml: Option<String>,
+    reader: Option<String>,
+    markdown: Option<String>,
+    text: Option<String>,
+    word_count: Option<usize>,
+    page_info: Option<PageInfo>,
+}
+
+pub fn render(args: &[&str]) -> anyhow::Result<()> {
+    let mut url = None;
+    let mut out = ".";
+    let mut formats = vec![];
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match *arg {
+            "--out" => {
+                out = args
+                    .next()
+                    .ok_or_else(|| anyhow::anyhow!("--out needs a directory"))?
+            }
+            "--formats" => {
+                let list = args
+                    .next()
+                    .ok_or_else(|| anyhow::anyhow!("--formats needs a list"))?;
+                for format in list.split(',').map(str::trim).filter(|f| !f.is_empty()) {
+                    let format: Format = serde_json::from_value(format.into())
+                        .map_err(|e| anyhow::anyhow!("--formats: {}", e))?;
+                    formats.push(format);
+                }
+            }
+            arg if url.is_none() && !arg.starts_with("--") => url = Some(arg),
+            arg => anyhow::bail!("Unexpected argument {}\n{}", arg, USAGE),
+        }
+    }
+    let url = url::Url::parse(url.ok_or_else(|| anyhow::anyhow!("Missing URL\n{}", USAGE))?)?;
+
+    let mut config = Config::from_env()?;
+    config.output_dir = std::path::PathBuf::from(out);
+    std::fs::create_dir_all(&config.output_dir)?;
+    let domain_config = config
+        .find_domain(&url)
+        .ok_or_else(|| anyhow::anyhow!("No domain config matches {}", url))?;
+    let mut chrome = ChromeDriver::new(&config.chrome_address, &config.chrome_kill_address)?;
+    let request = RenderRequest {
+        id: new_request_id(&url),
+        url,
+        options: RenderOptions {
+            formats,
+            ..Default::default()
+        },
+        notifiers: vec![],
+        user: None,
+        channel: None,
+        team: None,
+    };
+    let result = handle_request_once(&request, &config, &mut chrome)
+        .map_err(|e| anyhow::anyhow!("Render failed: {}", e))?;
+
+    // The renderer returns URLs of the files served by the server.
+    let prefix = format!("{}/static/", config.hostname);
+    let local = |url: &String| url.strip_prefix(&prefix).unwrap_or(url).to_string();
+    let screenshots = if result.screenshot_tiles.is_empty() {
+        result.png_url.iter().map(local).collect()
+    } else {
+        result.screenshot_tiles.iter().map(local).collect()
+    };
+    let manifest = Manifest {
+        url: result.orig_url.to_string(),
+        title: result.title,
+        rendered_at: chrono::Utc::now(),
+        domain_config: domain_config.name,
+        pdf: result.pdf_url.as_ref().map(local),
+        screenshots,
+        thumbnail: result.thumbnail_url.as_ref().map(local),
+        mhtml: result.mhtml_url.as_ref().map(local),
+        reader: result.reader_url.as_ref().map(local),
+        markdown: result.markdown_url.as_ref().map(local),
+        text: result.text_url.as_ref().map(local),
+        word_count: result.word_count,
+        page_info: result.page_info,
+    };
+    let manifest_path = config.output_dir.join("manifest.json");
+    std::fs::write(&manifest_path, serde_json::to_string_pretty(&manifest)?)?;
+
+    println!("Rendered {:?} ({})", manifest.title, manifest.url);
+    let files = [
+        &manifest.pdf,
+        &manifest.thumbnail,
+        &manifest.mhtml,
+        &manifest.reader,
+    ]
+    .into_iter()
+    .flatten()
+    .chain(&manifest.screenshots)
+    .chain(&manifest.markdown)
+    .chain(&manifest.text);
+    for file in files {
+        println!("  {}", config.output_dir.join(file).display());
+    }
+    println!("Manifest: {}", manifest_path.display());
+    Ok(())
+}
+
+// Subset of the import report returned by the API.
+#[derive(Deserialize)]
+struct ImportReport {
+    id: String,
+    done: bool,
+    total: usize,
+    pending: usize,
+    succeeded: Vec<ImportItem>,
+    failed: Vec<ImportItem>,
+    skipped: Vec<ImportItem>,
+    error: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ImportItem {
+    url: String,
+    error: Option<String>,
+}
+
+const IMPORT_POLL_INTERVAL: Duration = Duration::from_secs(5);
+// Gives up when no render finishes for this long, e.g. the server restarted.
+const IMPORT_STALL_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+
+// Imports run in the server, so that they share its render queue and archive.
+pub fn import(args: &[&str]) -> anyhow::Result<()> {
+    let mut source = None;
+    let mut server = None;
+    let mut delay: Option<u64> = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match *arg {
+            "--server" => {
+                server = Some(
+                    args.next()
+                        .ok_or_else(|| anyhow::anyhow!("--server needs a URL"))?
+                        .trim_end_matches('/'),
+                )
+            }
+            "--delay" => {
+                let seconds = args
+                    .next()
+                    .ok_or_else(|| anyhow::anyhow!("--delay needs seconds"))?;
+                delay = Some(
+                    seconds
+                        .parse()
+                        .map_err(|e| anyhow::anyhow!("--delay: {}", e))?,
+                );
+            }
+            arg if source.is_none() && !arg.starts_with("--") => source = Some(arg),
+            arg => anyhow::bail!("Unexpected argument {}\n{}", arg, USAGE),
+        }
+    }
+    let source = source.ok_or_else(|| anyhow::anyhow!("Missing file or URL\n{}", USAGE))?;
+
+    let config = Config::from_env()?;
+    let token = config
+        .api
+        .tokens
+        .first()
+        .ok_or_else(|| anyhow::anyhow!("No API token configured (UDRB_API_TOKENS)"))?;
+    let server = server.unwrap_or(&config.hostname);
+    // Local files are sent along, URLs are fetched by the server.
+    let mut body = serde_json::json!({ "delay_seconds": delay });
+    if bulk::is_http(source) {
+        body["source"] = source.into();
+    } else {
+        body["content"] = std::fs::read_to_string(source)
+            .map_err(|e| anyhow::anyhow!("{}: {}", source, e))?
+            .into();
+    }
+
+    let client = reqwest::blocking::Client::new();
+    let response = client
+        .post(format!("{}/api/imports", server))
+        .bearer_auth(&token.token)
+        .json(&body)
+        .send()?;
+    if !response.status().is_success() {
+        anyhow::bail!("Import failed: {} {}", response.status(), response.text()?);
+    }
+    let mut report: ImportReport = response.json()?;
+    println!(
+        "Import {} started, {} URLs ({} without a domain config are skipped)",
+        report.id,
+        report.total,
+        report.skipped.len()
+    );
+    let status_url = format!("{}/api/imports/{}", server, report.id);
+    let mut progress_at = std::time::Instant::now();
+    while !report.done {
+        if progress_at.elapsed() > IMPORT_STALL_TIMEOUT {
+            anyhow::bail!(
+                "Import {} stalled, {} URLs still pending, see {}",
+                report.id,
+                report.pending,
+                status_url
+            );
+        }
+        std::thread::sleep(IMPORT_POLL_INTERVAL);
+        let pending = report.pending;
+        report = client
+            .get(&status_url)
+            .bearer_auth(&token.token)
+            .send()?
+            .error_for_status()?
+            .json()?;
+        if report.pending != pending {
+            progress_at = std::time::Instant::now();
+        }
+        println!("  {} of {} pending", report.pending, report.total);
+    }
+
+    println!(
+        "Import {} done: {} succeeded, {} failed, {} skipped",
+        report.id,
+        report.succeeded.len(),
+        report.failed.len(),
+        report.skipped.len()
+    );
+    for item in report.failed.iter().chain(&report.skipped) {
+        println!("  {}: {}", item.url, item.error.as_deref().unwrap_or("?"));
+    }
+    if let Some(error) = report.error {
+        anyhow::bail!(
+            "Import stopped early, {} URLs not rendered: {}",
+            report.pending,
+            error
+        );
+    }
+    Ok(())
+}
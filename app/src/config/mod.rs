@@ -1,14 +1,96 @@
+mod profiles;
+
+use crate::chrome::{
+    BlockingOptions, CleanupOptions, DeviceProfile, FilterList, LoginStep, PdfOptions, RenderCss,
+    ScreenshotOptions, ScrollOptions, TotpOptions,
+};
+
 use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+
+use log::{error, info};
 
 #[derive(Clone, Debug, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct DomainConfig {
     pub name: String,
-    #[serde(with = "serde_regex")]
-    pub host: regex::Regex,
+    // URL patterns, all set ones must match. Without any, the config is a catch-all.
+    #[serde(default, with = "serde_regex")]
+    pub host: Option<regex::Regex>,
+    pub port: Option<u16>,
+    // Glob where * matches anything, e.g. "/live/*".
+    #[serde(default, deserialize_with = "deserialize_glob")]
+    pub path: Option<regex::Regex>,
+    #[serde(default, with = "serde_regex")]
+    pub path_regex: Option<regex::Regex>,
+    // Higher priority configs are tried first, file order otherwise.
+    #[serde(default)]
+    pub priority: i32,
+    // IP address URLs only match configs that allow them.
+    #[serde(default)]
+    pub allow_ip: bool,
     pub login_page: Option<String>,
     // TODO: Wrap in SecretString to hide from debug.
     pub login_script: Option<String>,
+    // Run after the login page and script, for logins spanning several pages.
+    #[serde(default)]
+    pub login_steps: Vec<LoginStep>,
+    // Two-factor codes for type_totp login steps.
+    pub totp: Option<TotpOptions>,
     pub render_script: Option<String>,
+    // JavaScript expression that must be truthy before capture, fails the render otherwise.
+    pub render_check: Option<String>,
+    // Stylesheet injected into the page, inline or {file: path}.
+    pub render_css: Option<RenderCss>,
+    #[serde(default)]
+    pub pdf: PdfOptions,
+    #[serde(default)]
+    pub screenshot: ScreenshotOptions,
+    // Browser defaults if unset.
+    pub device: Option<DeviceProfile>,
+    // Scroll through the page before capture to trigger lazy loading.
+    pub scroll: Option<ScrollOptions>,
+    // Consent dialogs of the common consent managers are handled unless
+    // disabled, other overlays are only removed if configured or opted in.
+    #[serde(default)]
+    pub cleanup: CleanupOptions,
+    // Ad and tracker blocking.
+    #[serde(default)]
+    pub blocking: BlockingOptions,
+    // Global and domain filter lists, resolved when loading the config.
+    #[serde(skip)]
+    pub filter_lists: Vec<Arc<FilterList>>,
+}
+
+fn deserialize_glob<'de, D>(deserializer: D) -> Result<Option<regex::Regex>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let glob: String = serde::Deserialize::deserialize(deserializer)?;
+    let pattern = regex::escape(&glob).replace(r"\*", ".*");
+    regex::Regex::new(&format!("^{}$", pattern))
+        .map(Some)
+        .map_err(serde::de::Error::custom)
+}
+
+impl DomainConfig {
+    pub fn matches(&self, url: &url::Url) -> bool {
+        let host = match url.host() {
+            Some(url::Host::Domain(domain)) => domain.to_string(),
+            Some(url::Host::Ipv4(ip)) if self.allow_ip => ip.to_string(),
+            Some(url::Host::Ipv6(ip)) if self.allow_ip => ip.to_string(),
+            _ => return false,
+        };
+        self.host.as_ref().is_none_or(|h| h.is_match(&host))
+            && self
+                .port
+                .is_none_or(|p| url.port_or_known_default() == Some(p))
+            && self.path.as_ref().is_none_or(|p| p.is_match(url.path()))
+            && self
+                .path_regex
+                .as_ref()
+                .is_none_or(|p| p.is_match(url.path()))
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -20,15 +102,146 @@ pub struct SlackConfig {
     pub max_age: chrono::TimeDelta,
 }
 
+#[derive(Clone)]
+pub struct MattermostConfig {
+    // Token of the slash command, the endpoint is disabled if unset.
+    pub token: Option<String>,
+}
+
+// Leaves out the token, so that it does not end up in logs.
+impl std::fmt::Debug for MattermostConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("MattermostConfig")
+            .field("enabled", &self.token.is_some())
+            .finish()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct DiscordConfig {
+    // Hex encoded application public key, the endpoint is disabled if unset.
+    pub public_key: Option<String>,
+    // Requests with an older X-Signature-Timestamp are rejected.
+    pub max_age: chrono::TimeDelta,
+}
+
+#[derive(Clone)]
+pub struct ApiToken {
+    // Name of the client, used as the requesting user.
+    pub name: String,
+    pub token: String,
+    // Admin tokens can also reload the config, set with a ":admin" suffix.
+    pub admin: bool,
+}
+
+// Leaves out the token, so that it does not end up in logs.
+impl std::fmt::Debug for ApiToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("ApiToken")
+            .field("name", &self.name)
+            .field("admin", &self.admin)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ApiConfig {
+    // If empty, the API is disabled.
+    pub tokens: Vec<ApiToken>,
+}
+
+#[derive(Clone)]
+pub struct ArchiveUser {
+    pub name: String,
+    pub password: String,
+}
+
+// Leaves out the password, so that it does not end up in logs.
+impl std::fmt::Debug for ArchiveUser {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("ArchiveUser")
+            .field("name", &self.name)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ArchiveConfig {
+    // Basic auth logins for the archive pages. If empty, the pages are disabled.
+    pub users: Vec<ArchiveUser>,
+}
+
+impl ArchiveConfig {
+    // Other pages and messages only link to the archive if this is true.
+    pub fn is_enabled(&self) -> bool {
+        !self.users.is_empty()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SmtpConfig {
+    // Local relay as host:port, email notifications are disabled if unset.
+    pub address: Option<String>,
+    pub from: String,
+}
+
+#[derive(Clone)]
+pub struct WebhookConfig {
+    // Called after every render.
+    pub urls: Vec<String>,
+    // If set, payloads are signed like Slack signs its requests.
+    pub secret: Option<String>,
+}
+
+// Leaves out the secret, so that it does not end up in logs.
+impl std::fmt::Debug for WebhookConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("WebhookConfig")
+            .field("urls", &self.urls)
+            .field("signed", &self.secret.is_some())
+            .finish()
+    }
+}
+
+// Domain configs shared by all clones of the config, replaced on reload.
+// Renders in progress keep using the configs they started with.
+#[derive(Clone, Debug, Default)]
+pub struct Domains(Arc<RwLock<Arc<Vec<DomainConfig>>>>);
+
+impl Domains {
+    pub fn get(&self) -> Arc<Vec<DomainConfig>> {
+        self.0.read().unwrap().clone()
+    }
+
+    fn set(&self, domains: Vec<DomainConfig>) {
+        *self.0.write().unwrap() = Arc::new(domains);
+    }
+
+    // First matching config, they are sorted by priority when loaded.
+    fn find(&self, url: &url::Url) -> Option<DomainConfig> {
+        self.get().iter().find(|dc| dc.matches(url)).cloned()
+    }
+}
+
 // TODO: Maybe Arc would be better than cloning.
 #[derive(Clone, Debug)]
 pub struct Config {
     pub hostname: String,
     pub output_dir: std::path::PathBuf,
+    pub archive_dir: std::path::PathBuf,
     pub chrome_address: String,
     pub chrome_kill_address: String,
     pub slack: SlackConfig,
-    pub domains: Vec<DomainConfig>,
+    pub mattermost: MattermostConfig,
+    pub discord: DiscordConfig,
+    pub api: ApiConfig,
+    pub archive: ArchiveConfig,
+    pub smtp: SmtpConfig,
+    pub webhooks: WebhookConfig,
+    pub domain_config_path: std::path::PathBuf,
+    // Global filter lists from UDRB_FILTER_LISTS.
+    pub filter_lists: Vec<Arc<FilterList>>,
+    pub domains: Domains,
 }
 
 // Helper to include the variable name in the error message.
@@ -36,38 +249,332 @@ fn get_env_var(name: &str) -> anyhow::Result<String> {
     std::env::var(name).map_err(|e| anyhow::anyhow!("{}: {}", name, e))
 }
 
+// Maximum age of signed requests in seconds, 120 if unset.
+fn get_max_age(name: &str) -> chrono::TimeDelta {
+    get_env_var(name)
+        .as_deref()
+        .ok()
+        .or(Some("120"))
+        .map(str::parse::<u32>)
+        .and_then(Result::ok)
+        .map(u32::try_into)
+        .and_then(Result::ok)
+        .and_then(chrono::TimeDelta::try_seconds)
+        .unwrap_or_else(|| panic!("Config {} is invalid", name))
+}
+
+// Comma separated list of "name:secret" pairs, empty if unset.
+fn get_env_pairs(name: &str) -> anyhow::Result<Vec<(String, String)>> {
+    get_env_var(name)
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|t| !t.is_empty())
+        .map(|t| match t.split_once(':') {
+            Some((key, secret)) if !key.is_empty() && !secret.is_empty() => {
+                Ok((key.to_string(), secret.to_string()))
+            }
+            _ => Err(anyhow::anyhow!("{}: expected name:secret pairs", name)),
+        })
+        .collect()
+}
+
 impl Config {
     pub fn from_env() -> anyhow::Result<Config> {
         let hostname = get_env_var("UDRB_HOSTNAME")?;
-        let output_dir = get_env_var("UDRB_OUTPUT_DIR")?;
+        let output_dir = std::path::PathBuf::from_str(&get_env_var("UDRB_OUTPUT_DIR")?)?;
+        // Dot directories are not served by the /static handler.
+        let archive_dir = match get_env_var("UDRB_ARCHIVE_DIR") {
+            Ok(dir) => std::path::PathBuf::from_str(&dir)?,
+            Err(_) => output_dir.join(".archive"),
+        };
         let chrome_address = get_env_var("UDRB_CHROME_ADDRESS")?;
         let chrome_kill_address = get_env_var("UDRB_CHROME_KILL_ADDRESS")?;
 
         let slack = SlackConfig {
             secret: get_env_var("UDRB_SLACK_SECRET").ok(),
-            max_age: get_env_var("UDRB_SLACK_MAX_AGE_SECONDS")
-                .as_deref()
-                .ok()
-                .or(Some("120"))
-                .map(str::parse::<u32>)
-                .and_then(Result::ok)
-                .map(u32::try_into)
-                .and_then(Result::ok)
-                .and_then(chrono::TimeDelta::try_seconds)
-                .expect("Config max_age_seconds is invalid"),
+            max_age: get_max_age("UDRB_SLACK_MAX_AGE_SECONDS"),
         };
 
-        let domain_config_path = get_env_var("UDRB_DOMAIN_CONFIG")?;
-        let domain_config = std::fs::read_to_string(domain_config_path)?;
-        let domains: Vec<DomainConfig> = serde_yaml::from_str(&domain_config)?;
+        let mattermost = MattermostConfig {
+            token: get_env_var("UDRB_MATTERMOST_TOKEN").ok(),
+        };
+        let discord = DiscordConfig {
+            public_key: get_env_var("UDRB_DISCORD_PUBLIC_KEY").ok(),
+            max_age: get_max_age("UDRB_DISCORD_MAX_AGE_SECONDS"),
+        };
+
+        let api = ApiConfig {
+            tokens: get_env_pairs("UDRB_API_TOKENS")?
+                .into_iter()
+                .map(|(name, token)| match token.strip_suffix(":admin") {
+                    Some(token) => ApiToken {
+                        name,
+                        token: token.to_string(),
+                        admin: true,
+                    },
+                    None => ApiToken {
+                        name,
+                        token,
+                        admin: false,
+                    },
+                })
+                .collect(),
+        };
+        let archive = ArchiveConfig {
+            users: get_env_pairs("UDRB_ARCHIVE_USERS")?
+                .into_iter()
+                .map(|(name, password)| ArchiveUser { name, password })
+                .collect(),
+        };
+
+        let smtp = SmtpConfig {
+            address: get_env_var("UDRB_SMTP_ADDRESS").ok(),
+            from: get_env_var("UDRB_SMTP_FROM").unwrap_or_else(|_| "udrb@localhost".to_string()),
+        };
+
+        // Comma separated list of URLs.
+        let webhooks = WebhookConfig {
+            urls: get_env_var("UDRB_WEBHOOKS")
+                .unwrap_or_default()
+                .split(',')
+                .map(str::trim)
+                .filter(|u| !u.is_empty())
+                .map(|u| {
+                    url::Url::parse(u)
+                        .map(|_| u.to_string())
+                        .map_err(|e| anyhow::anyhow!("UDRB_WEBHOOKS: {}: {}", u, e))
+                })
+                .collect::<anyhow::Result<_>>()?,
+            secret: get_env_var("UDRB_WEBHOOK_SECRET").ok(),
+        };
+
+        // Comma separated list of filter list files, applied to all domains.
+        let filter_lists: Vec<Arc<FilterList>> = get_env_var("UDRB_FILTER_LISTS")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|p| !p.is_empty())
+            .map(|p| FilterList::load(p).map(Arc::new))
+            .collect::<anyhow::Result<_>>()?;
+
+        let domain_config_path = std::path::PathBuf::from(get_env_var("UDRB_DOMAIN_CONFIG")?);
+        let domains = Domains::default();
+        domains.set(load_domains(&domain_config_path, &filter_lists)?);
 
         Ok(Config {
             hostname,
-            output_dir: std::path::PathBuf::from_str(&output_dir)?,
+            output_dir,
+            archive_dir,
             chrome_address,
             chrome_kill_address,
             slack,
+            mattermost,
+            discord,
+            api,
+            archive,
+            smtp,
+            webhooks,
+            domain_config_path,
+            filter_lists,
             domains,
         })
     }
+
+    // Config used to render the URL, None if not supported.
+    pub fn find_domain(&self, url: &url::Url) -> Option<DomainConfig> {
+        self.domains.find(url)
+    }
+
+    // Reads domains.yaml again, for all clones of the config. The current
+    // domains are kept if it is not valid.
+    pub fn reload_domains(&self) -> anyhow::Result<usize> {
+        let domains = load_domains(&self.domain_config_path, &self.filter_lists)?;
+        let count = domains.len();
+        self.domains.set(domains);
+        info!("Reloaded {} domain configs", count);
+        Ok(count)
+    }
+
+    // Reloads domains.yaml whenever it is modified.
+    pub fn watch_domains(&self) {
+        let config = self.clone();
+        let modified =
+            |path: &std::path::Path| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+        std::thread::spawn(move || {
+            let mut last_modified = modified(&config.domain_config_path);
+            loop {
+                std::thread::sleep(WATCH_INTERVAL);
+                let current = modified(&config.domain_config_path);
+                if current == last_modified {
+                    continue;
+                }
+                last_modified = current;
+                if let Err(e) = config.reload_domains() {
+                    error!(
+                        "Failed to reload domain configs, keeping the old ones: {:?}",
+                        e
+                    );
+                }
+            }
+        });
+    }
+}
+
+const WATCH_INTERVAL: std::time::Duration = std::time::Duration::from_secs(2);
+
+// Reads, validates and prepares the domain configs.
+fn load_domains(
+    path: &std::path::Path,
+    filter_lists: &[Arc<FilterList>],
+) -> anyhow::Result<Vec<DomainConfig>> {
+    let domain_config =
+        std::fs::read_to_string(path).map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))?;
+    let mut domains = profiles::parse_domains(&domain_config)?;
+    // Stable, so equal priorities keep the file order.
+    domains.sort_by_key(|d| std::cmp::Reverse(d.priority));
+    for css in domains.iter_mut().filter_map(|d| d.render_css.as_mut()) {
+        css.load()?;
+    }
+    for domain in domains.iter_mut() {
+        if let Some(ref mut totp) = domain.totp {
+            totp.load()
+                .map_err(|e| anyhow::anyhow!("{}: {}", domain.name, e))?;
+        }
+    }
+    for domain in domains.iter_mut().filter(|d| d.blocking.enabled) {
+        domain.filter_lists = filter_lists.to_vec();
+        for path in &domain.blocking.lists {
+            domain.filter_lists.push(Arc::new(FilterList::load(path)?));
+        }
+        if !domain.blocking.rules.is_empty() {
+            let name = format!("{} rules", domain.name);
+            let rules = domain.blocking.rules.iter().map(String::as_str);
+            domain
+                .filter_lists
+                .push(Arc::new(FilterList::parse(&name, rules)));
+        }
+    }
+    Ok(domains)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Loads the configs like the server does, from a file named after the test.
+    fn domains(test: &str, yaml: &str) -> Domains {
+        let path = std::env::temp_dir().join(format!("udrb-{}-{}.yaml", test, std::process::id()));
+        std::fs::write(&path, yaml).unwrap();
+        let loaded = load_domains(&path, &[]);
+        std::fs::remove_file(&path).unwrap();
+        let domains = Domains::default();
+        domains.set(loaded.unwrap());
+        domains
+    }
+
+    fn find(domains: &Domains, url: &str) -> Option<String> {
+        domains.find(&url::Url::parse(url).unwrap()).map(|d| d.name)
+    }
+
+    #[test]
+    fn host_port_and_path() {
+        let domains = domains(
+            "host_port_and_path",
+            r#"
+- {name: Live, host: "(^|\\.)example\\.com$", path: "/live/*"}
+- {name: Numeric, host: "(^|\\.)example\\.com$", path_regex: "^/[0-9]+$"}
+- {name: Dev, host: "^example\\.com$", port: 8080}
+- {name: Example, host: "(^|\\.)example\\.com$", port: 443}
+"#,
+        );
+        let live = Some("Live".to_string());
+        assert_eq!(find(&domains, "https://example.com/live/123"), live);
+        assert_eq!(find(&domains, "https://www.example.com/live/a/b"), live);
+        // Globs match the whole path, unlike the regexes.
+        assert_eq!(
+            find(&domains, "https://example.com/blog/live/1").as_deref(),
+            Some("Example")
+        );
+        assert_eq!(
+            find(&domains, "https://example.com/live").as_deref(),
+            Some("Example")
+        );
+        assert_eq!(
+            find(&domains, "https://example.com/123").as_deref(),
+            Some("Numeric")
+        );
+        assert_eq!(
+            find(&domains, "http://example.com:8080/a").as_deref(),
+            Some("Dev")
+        );
+        // The default port of the scheme counts.
+        assert_eq!(
+            find(&domains, "https://example.com:443/a").as_deref(),
+            Some("Example")
+        );
+        assert_eq!(find(&domains, "http://example.com/a"), None);
+        assert_eq!(find(&domains, "https://example.org/live/1"), None);
+        assert_eq!(find(&domains, "https://notexample.com/a"), None);
+    }
+
+    #[test]
+    fn glob_escapes_regex() {
+        let domains = domains(
+            "glob_escapes_regex",
+            "- {name: Glob, path: \"/a.b/*.html\"}",
+        );
+        assert!(find(&domains, "https://example.com/a.b/x/y.html").is_some());
+        assert!(find(&domains, "https://example.com/axb/y.html").is_none());
+        assert!(find(&domains, "https://example.com/a.b/y.htm").is_none());
+    }
+
+    #[test]
+    fn allow_ip() {
+        let domains = domains(
+            "allow_ip",
+            r#"
+- {name: Local, host: "^127\\.", allow_ip: true}
+- {name: Any}
+"#,
+        );
+        assert_eq!(
+            find(&domains, "http://127.0.0.1:8000/").as_deref(),
+            Some("Local")
+        );
+        assert_eq!(find(&domains, "http://10.0.0.1/"), None);
+        assert_eq!(find(&domains, "http://[::1]/"), None);
+        assert_eq!(
+            find(&domains, "https://example.com/").as_deref(),
+            Some("Any")
+        );
+    }
+
+    #[test]
+    fn priority() {
+        let domains = domains(
+            "priority",
+            r#"
+- {name: Fallback, priority: -1}
+- {name: Site, host: "example\\.com$"}
+- {name: Other, host: "example\\.com$"}
+- {name: Blog, host: "example\\.com$", path: "/blog/*", priority: 10}
+"#,
+        );
+        assert_eq!(
+            find(&domains, "https://example.com/blog/1").as_deref(),
+            Some("Blog")
+        );
+        // Equal priorities keep the file order.
+        assert_eq!(
+            find(&domains, "https://example.com/a").as_deref(),
+            Some("Site")
+        );
+        assert_eq!(
+            find(&domains, "https://example.org/a").as_deref(),
+            Some("Fallback")
+        );
+        let names: Vec<_> = domains.get().iter().map(|d| d.name.clone()).collect();
+        assert_eq!(names, ["Blog", "Site", "Other", "Fallback"]);
+    }
 }
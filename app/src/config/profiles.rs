@@ -0,0 +1,260 @@
+// Reusable profiles in domains.yaml. Domains (and other profiles) can extend
+// profiles, inheriting all their options:
+//
+//   profiles:
+//     consent: {render_script: "...", cleanup: {click: ["#reject"]}}
+//   domains:
+//     - name: Example
+//       host: "example.com$"
+//       extends: consent
+//
+// A plain list of domains without profiles is accepted too.
+
+use super::DomainConfig;
+
+use std::collections::BTreeMap;
+
+use anyhow::{bail, format_err};
+use serde_yaml::{Mapping, Value};
+
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+enum DomainsFile {
+    Domains(Vec<Value>),
+    WithProfiles {
+        #[serde(default)]
+        profiles: BTreeMap<String, Value>,
+        domains: Vec<Value>,
+    },
+}
+
+// Scripts are concatenated (inherited first) instead of overridden, so that
+// domains can add to the scripts of their profiles.
+const CONCATENATED_KEYS: &[&str] = &["login_script", "render_script"];
+
+pub fn parse_domains(yaml: &str) -> anyhow::Result<Vec<DomainConfig>> {
+    let (profiles, domains) = match serde_yaml::from_str(yaml)? {
+        DomainsFile::Domains(domains) => (BTreeMap::new(), domains),
+        DomainsFile::WithProfiles { profiles, domains } => (profiles, domains),
+    };
+
+    // Profiles are validated even if unused, with their name standing in for the domain name.
+    for (name, profile) in &profiles {
+        let mut resolved = resolve(profile, &profiles, &mut vec![name.clone()])
+            .map_err(|e| format_err!("Profile {}: {}", name, e))?;
+        resolved
+            .entry("name".into())
+            .or_insert_with(|| name.as_str().into());
+        deserialize(resolved).map_err(|e| format_err!("Profile {}: {}", name, e))?;
+    }
+
+    domains
+        .iter()
+        .enumerate()
+        .map(|(i, domain)| {
+            let name = match domain.get("name").and_then(Value::as_str) {
+                Some(name) => name.to_string(),
+                None => format!("#{}", i + 1),
+            };
+            resolve(domain, &profiles, &mut vec![])
+                .and_then(deserialize)
+                .map_err(|e| format_err!("Domain {}: {}", name, e))
+        })
+        .collect()
+}
+
+// Deserializes the domain, naming the path of the invalid field on errors.
+fn deserialize(mapping: Mapping) -> anyhow::Result<DomainConfig> {
+    serde_path_to_error::deserialize(Value::Mapping(mapping)).map_err(|e| {
+        let path = e.path().to_string();
+        match path.as_str() {
+            "." => format_err!("{}", e.into_inner()),
+            _ => format_err!("{}: {}", path, e.into_inner()),
+        }
+    })
+}
+
+// Returns the entry merged over all the profiles it extends. The stack has
+// the profiles being resolved, to detect cycles.
+fn resolve(
+    entry: &Value,
+    profiles: &BTreeMap<String, Value>,
+    stack: &mut Vec<String>,
+) -> anyhow::Result<Mapping> {
+    let mut entry = match entry {
+        Value::Mapping(mapping) => mapping.clone(),
+        _ => bail!("Expected a mapping"),
+    };
+    let extends = match entry.remove("extends") {
+        None => vec![],
+        Some(Value::String(name)) => vec![name],
+        Some(Value::Sequence(names)) => names
+            .into_iter()
+            .map(|name| match name {
+                Value::String(name) => Ok(name),
+                _ => Err(format_err!("extends: Expected profile names")),
+            })
+            .collect::<anyhow::Result<_>>()?,
+        Some(_) => bail!("extends: Expected a profile name or a list of them"),
+    };
+
+    let mut resolved = Mapping::new();
+    for name in extends {
+        let profile = profiles
+            .get(&name)
+            .ok_or_else(|| format_err!("extends: Unknown profile {}", name))?;
+        if stack.contains(&name) {
+            bail!("extends: Cycle {} -> {}", stack.join(" -> "), name);
+        }
+        stack.push(name);
+        let parent = resolve(profile, profiles, stack)?;
+        stack.pop();
+        merge(&mut resolved, parent, true);
+    }
+    merge(&mut resolved, entry, true);
+    Ok(resolved)
+}
+
+// Merges nested mappings, other values are replaced.
+fn merge(base: &mut Mapping, over: Mapping, top_level: bool) {
+    for (key, value) in over {
+        let concatenate = top_level
+            && key
+                .as_str()
+                .is_some_and(|key| CONCATENATED_KEYS.contains(&key));
+        match (base.get_mut(&key), value) {
+            (Some(Value::Mapping(base)), Value::Mapping(value)) => merge(base, value, false),
+            (Some(Value::String(base)), Value::String(value)) if concatenate => {
+                base.push_str(";\n");
+                base.push_str(&value);
+            }
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(yaml: &str) -> String {
+        parse_domains(yaml).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn unknown_nested_fields() {
+        let cases = [
+            (
+                "pdf: {landscpe: true}",
+                "pdf.landscpe: unknown field `landscpe`",
+            ),
+            (
+                "pdf: {margins: {topp: 1}}",
+                "pdf.margins.topp: unknown field",
+            ),
+            (
+                "screenshot: {qualty: 50}",
+                "screenshot.qualty: unknown field",
+            ),
+            ("scroll: {delay: 500}", "scroll.delay: unknown field"),
+            ("cleanup: {enable: false}", "cleanup.enable: unknown field"),
+            (
+                "blocking: {enable: false}",
+                "blocking.enable: unknown field",
+            ),
+            (
+                "totp: {secret: 'env:X', digit: 8}",
+                "totp.digit: unknown field",
+            ),
+            (
+                "login_steps: [{click: '#a', timout_ms: 5}]",
+                "login_steps[0]: unknown field `timout_ms`",
+            ),
+        ];
+        for (option, expected) in cases {
+            let error = error(&format!("- name: Typo\n  {}", option));
+            assert!(
+                error.starts_with(&format!("Domain Typo: {}", expected)),
+                "{}",
+                error
+            );
+        }
+        let steps = "login_steps: [{click: '#a', timeout_ms: 5}, {wait_for_navigation: }]";
+        assert!(parse_domains(&format!("- name: Ok\n  {}", steps)).is_ok());
+    }
+
+    #[test]
+    fn multi_level_extends() {
+        let yaml = r#"
+profiles:
+  base: {render_script: "a()", pdf: {landscape: true, scale: 0.5}, priority: 1}
+  news: {extends: base, render_script: "b()", pdf: {scale: 0.8}}
+  login: {login_script: "login()", priority: 2}
+domains:
+  - name: Example
+    extends: [news, login]
+    render_script: "c()"
+    pdf: {print_background: true}
+"#;
+        let domains = parse_domains(yaml).unwrap();
+        let domain = &domains[0];
+        assert_eq!(domain.name, "Example");
+        // Scripts run inherited first, nested options are merged.
+        assert_eq!(domain.render_script.as_deref(), Some("a();\nb();\nc()"));
+        assert_eq!(domain.login_script.as_deref(), Some("login()"));
+        assert_eq!(domain.pdf.landscape, Some(true));
+        assert_eq!(domain.pdf.scale, Some(0.8));
+        assert_eq!(domain.pdf.print_background, Some(true));
+        // Later profiles override earlier ones, the domain overrides all.
+        assert_eq!(domain.priority, 2);
+    }
+
+    #[test]
+    fn domain_overrides_profile() {
+        let yaml = r#"
+profiles:
+  base: {priority: 5, allow_ip: true, login_page: "https://example.com/login"}
+domains:
+  - {name: A, extends: base, priority: 1}
+  - {name: B, extends: base}
+"#;
+        let domains = parse_domains(yaml).unwrap();
+        assert_eq!(domains[0].priority, 1);
+        assert_eq!(domains[1].priority, 5);
+        assert!(domains[0].allow_ip && domains[1].allow_ip);
+        assert!(domains[0].login_page.is_some());
+    }
+
+    #[test]
+    fn extends_errors() {
+        let cycle = r#"
+profiles:
+  a: {extends: b}
+  b: {extends: a}
+domains: []
+"#;
+        assert_eq!(error(cycle), "Profile a: extends: Cycle a -> b -> a");
+        let self_cycle = "profiles: {a: {extends: [a]}}\ndomains: []";
+        assert_eq!(error(self_cycle), "Profile a: extends: Cycle a -> a");
+        let unknown = "profiles: {a: {}}\ndomains: [{name: X, extends: [a, b]}]";
+        assert_eq!(error(unknown), "Domain X: extends: Unknown profile b");
+        let unused = "profiles: {a: {extends: missing}}\ndomains: []";
+        assert_eq!(error(unused), "Profile a: extends: Unknown profile missing");
+        let invalid = "domains: [{name: X, extends: {a: 1}}]";
+        assert_eq!(
+            error(invalid),
+            "Domain X: extends: Expected a profile name or a list of them"
+        );
+    }
+
+    #[test]
+    fn plain_list() {
+        let domains = parse_domains("- {name: A}\n- {name: B}").unwrap();
+        assert_eq!(domains.len(), 2);
+        // The name is required, unnamed domains are numbered in errors.
+        let unnamed = error("- {name: A}\n- {host: example.com}");
+        assert!(unnamed.starts_with("Domain #2: "), "{}", unnamed);
+    }
+}
@@ -0,0 +1,506 @@
+// Discord interactions endpoint for the /udrb application command.
+//
+// The command has two subcommands: "capture" with a "url" option and
+// "search" with a "query" option. Captures are answered with a deferred
+// response which is edited once the render is finished.
+
+use crate::archive::Archive;
+use crate::chrome::reading_time_minutes;
+use crate::config::{Config, DiscordConfig};
+use crate::notify::{self, Notifier};
+use crate::renderer::{new_request_id, RenderError, RenderRequest, RenderResult, RenderSender};
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use chrono::{TimeZone, Utc};
+use log::error;
+use rocket::data::{Data, ToByteUnit};
+use rocket::http::Status;
+use rocket::request::{self, FromRequest, Outcome, Request};
+use rocket::serde::json::Json;
+use rocket::State;
+use serde::{Deserialize, Serialize};
+
+const DISCORD_API: &str = "https://discord.com/api/v10";
+
+// Number of search results shown, Discord allows at most 10 embeds.
+const SEARCH_RESULT_COUNT: usize = 5;
+// Responses can only be edited this long after the interaction.
+const INTERACTION_TOKEN_TTL: Duration = Duration::from_secs(15 * 60);
+// Discord rejects messages with longer embed texts (in characters).
+const EMBED_TITLE_LENGTH: usize = 256;
+const EMBED_DESCRIPTION_LENGTH: usize = 4096;
+
+// Interaction and response types, see
+// https://discord.com/developers/docs/interactions/receiving-and-responding
+const INTERACTION_PING: u8 = 1;
+const INTERACTION_APPLICATION_COMMAND: u8 = 2;
+const RESPONSE_PONG: u8 = 1;
+const RESPONSE_CHANNEL_MESSAGE: u8 = 4;
+const RESPONSE_DEFERRED_CHANNEL_MESSAGE: u8 = 5;
+const FLAG_EPHEMERAL: u32 = 1 << 6;
+
+#[derive(Debug, Deserialize)]
+struct Interaction {
+    #[serde(rename = "type")]
+    type_: u8,
+    application_id: String,
+    token: String,
+    #[serde(default)]
+    data: Option<CommandData>,
+    guild_id: Option<String>,
+    channel: Option<Channel>,
+    member: Option<Member>,
+    user: Option<User>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CommandData {
+    name: String,
+    #[serde(default)]
+    options: Vec<CommandOption>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CommandOption {
+    name: String,
+    #[serde(default)]
+    value: Option<serde_json::Value>,
+    #[serde(default)]
+    options: Vec<CommandOption>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Channel {
+    name: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Member {
+    user: User,
+}
+
+#[derive(Debug, Deserialize)]
+struct User {
+    id: String,
+}
+
+#[derive(Debug, Serialize, Default)]
+pub struct Embed {
+    title: String,
+    url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    author: Option<EmbedAuthor>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    thumbnail: Option<EmbedImage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    footer: Option<EmbedFooter>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EmbedAuthor {
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    icon_url: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EmbedImage {
+    url: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EmbedFooter {
+    text: String,
+}
+
+// Action row (type 1) with link buttons (type 2, style 5).
+#[derive(Debug, Serialize)]
+pub struct Component {
+    #[serde(rename = "type")]
+    type_: u8,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    style: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    label: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    url: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    components: Vec<Component>,
+}
+
+#[derive(Debug, Serialize, Default)]
+pub struct MessageData {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    embeds: Vec<Embed>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    components: Vec<Component>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    flags: Option<u32>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct InteractionResponse {
+    #[serde(rename = "type")]
+    type_: u8,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<MessageData>,
+}
+
+fn ephemeral(content: String) -> InteractionResponse {
+    InteractionResponse {
+        type_: RESPONSE_CHANNEL_MESSAGE,
+        data: Some(MessageData {
+            content: Some(content),
+            flags: Some(FLAG_EPHEMERAL),
+            ..Default::default()
+        }),
+    }
+}
+
+fn link_buttons(links: &[(&Option<String>, &str)]) -> Vec<Component> {
+    let buttons: Vec<_> = links
+        .iter()
+        .filter_map(|(url, label)| {
+            url.as_ref().map(|url| Component {
+                type_: 2,
+                style: Some(5),
+                label: Some(label.to_string()),
+                url: Some(url.clone()),
+                components: vec![],
+            })
+        })
+        // At most 5 buttons fit in a row.
+        .take(5)
+        .collect();
+    if buttons.is_empty() {
+        return vec![];
+    }
+    vec![Component {
+        type_: 1,
+        style: None,
+        label: None,
+        url: None,
+        components: buttons,
+    }]
+}
+
+// Shortens the text to at most max_length characters, ending with an ellipsis.
+fn truncate(text: String, max_length: usize) -> String {
+    if text.chars().count() <= max_length {
+        return text;
+    }
+    let mut text: String = text.chars().take(max_length - 1).collect();
+    text.push('…');
+    text
+}
+
+fn search_reply(query: &str, archive: &Archive) -> InteractionResponse {
+    let hits = archive.search(query, SEARCH_RESULT_COUNT);
+    if hits.is_empty() {
+        return ephemeral(format!("No captures found for _{}_.", query));
+    }
+    InteractionResponse {
+        type_: RESPONSE_CHANNEL_MESSAGE,
+        data: Some(MessageData {
+            content: Some(format!("Captures matching _{}_:", query)),
+            embeds: hits
+                .into_iter()
+                .map(|hit| {
+                    let capture = hit.capture;
+                    let links: Vec<_> = [
+                        (&capture.pdf_url, "PDF"),
+                        (&capture.png_url, "Screenshot"),
+                        (&capture.reader_url, "Reader"),
+                    ]
+                    .into_iter()
+                    .filter_map(|(url, name)| url.as_ref().map(|u| format!("[{}]({})", name, u)))
+                    .collect();
+                    Embed {
+                        description: Some(truncate(
+                            format!("{}\n{}", hit.snippet, links.join(" · ")),
+                            EMBED_DESCRIPTION_LENGTH,
+                        )),
+                        footer: Some(EmbedFooter {
+                            text: capture.created.format("%Y-%m-%d").to_string(),
+                        }),
+                        title: truncate(capture.title, EMBED_TITLE_LENGTH),
+                        url: capture.orig_url.to_string(),
+                        ..Default::default()
+                    }
+                })
+                .collect(),
+            flags: Some(FLAG_EPHEMERAL),
+            ..Default::default()
+        }),
+    }
+}
+
+// Verifies the Ed25519 signature of the request.
+pub struct SignatureVerifier {
+    timestamp: String,
+    signature: String,
+    config: DiscordConfig,
+}
+
+#[derive(Debug)]
+pub enum VerifierError {
+    ConfigError,
+    MissingHeaders,
+    BadBody,
+    SignatureInvalid,
+    TimestampTooDifferent,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for SignatureVerifier {
+    type Error = VerifierError;
+
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let config = match req.guard::<&State<Config>>().await {
+            Outcome::Success(config) => config.discord.clone(),
+            _ => return Outcome::Error((Status::InternalServerError, VerifierError::ConfigError)),
+        };
+        let timestamp = req.headers().get_one("X-Signature-Timestamp");
+        let signature = req.headers().get_one("X-Signature-Ed25519");
+        match (timestamp, signature) {
+            (Some(t), Some(s)) => Outcome::Success(SignatureVerifier {
+                timestamp: t.to_string(),
+                signature: s.to_string(),
+                config,
+            }),
+            _ => Outcome::Error((Status::Unauthorized, VerifierError::MissingHeaders)),
+        }
+    }
+}
+
+impl SignatureVerifier {
+    async fn parse(&self, raw_data: Data<'_>) -> Result<Interaction, VerifierError> {
+        let data = raw_data
+            // Interactions are small, but can include resolved users etc.
+            .open(100.kibibytes())
+            .into_string()
+            .await
+            .map_err(|_| VerifierError::BadBody)?;
+        if !data.is_complete() {
+            return Err(VerifierError::BadBody);
+        }
+
+        let public_key: [u8; 32] = self
+            .config
+            .public_key
+            .as_deref()
+            .and_then(|k| hex::decode(k).ok())
+            .and_then(|k| k.try_into().ok())
+            .ok_or(VerifierError::ConfigError)?;
+        let public_key = ed25519_dalek::VerifyingKey::from_bytes(&public_key)
+            .map_err(|_| VerifierError::ConfigError)?;
+        let signature: [u8; 64] = hex::decode(&self.signature)
+            .ok()
+            .and_then(|s| s.try_into().ok())
+            .ok_or(VerifierError::SignatureInvalid)?;
+        let message = format!("{}{}", self.timestamp, data.as_str());
+        public_key
+            .verify_strict(
+                message.as_bytes(),
+                &ed25519_dalek::Signature::from_bytes(&signature),
+            )
+            .map_err(|_| {
+                error!("Rejecting bad Discord signature");
+                VerifierError::SignatureInvalid
+            })?;
+
+        // Verify timestamp, the signature alone does not prevent replays.
+        let request_time = self
+            .timestamp
+            .parse()
+            .ok()
+            .and_then(|t| Utc.timestamp_opt(t, 0).single())
+            .ok_or(VerifierError::SignatureInvalid)?;
+        let difference = Utc::now().signed_duration_since(request_time).abs();
+        if difference > self.config.max_age {
+            error!("Rejecting timestamp with large diff: {:?}", difference);
+            return Err(VerifierError::TimestampTooDifferent);
+        }
+
+        serde_json::from_str(&data).map_err(|_| VerifierError::BadBody)
+    }
+}
+
+fn option_value<'a>(options: &'a [CommandOption], name: &str) -> Option<&'a str> {
+    options
+        .iter()
+        .find(|o| o.name == name)
+        .and_then(|o| o.value.as_ref())
+        .and_then(|v| v.as_str())
+}
+
+#[rocket::post("/interactions", data = "<data>")]
+pub async fn interactions(
+    verifier: SignatureVerifier,
+    data: Data<'_>,
+    sender: &State<RenderSender>,
+    archive: &State<Arc<Archive>>,
+) -> Result<Json<InteractionResponse>, (Status, &'static str)> {
+    let interaction = verifier.parse(data).await.map_err(|e| match e {
+        VerifierError::BadBody => (Status::BadRequest, "Bad request"),
+        _ => (Status::Unauthorized, "Invalid request signature"),
+    })?;
+
+    // Discord checks the endpoint with pings when it is configured.
+    if interaction.type_ == INTERACTION_PING {
+        return Ok(Json(InteractionResponse {
+            type_: RESPONSE_PONG,
+            data: None,
+        }));
+    }
+    let usage =
+        ephemeral("Usage: `/udrb capture url:http://...` or `/udrb search query:...`".to_string());
+    if interaction.type_ != INTERACTION_APPLICATION_COMMAND {
+        return Ok(Json(usage));
+    }
+    let command = match interaction.data {
+        Some(ref data) if data.name == "udrb" && !data.options.is_empty() => &data.options[0],
+        _ => return Ok(Json(usage)),
+    };
+
+    if command.name == "search" {
+        return Ok(Json(match option_value(&command.options, "query") {
+            Some(query) => search_reply(query.trim(), archive),
+            None => usage,
+        }));
+    }
+    let url = match option_value(&command.options, "url").map(|u| url::Url::parse(u.trim())) {
+        Some(Ok(url)) if command.name == "capture" => url,
+        _ => return Ok(Json(usage)),
+    };
+
+    let user = interaction
+        .member
+        .map(|m| m.user)
+        .or(interaction.user)
+        .map(|u| u.id);
+    sender
+        .render(RenderRequest {
+            id: new_request_id(&url),
+            url,
+            options: Default::default(),
+            notifiers: vec![Box::new(DiscordNotifier {
+                application_id: interaction.application_id,
+                token: interaction.token,
+                received: Instant::now(),
+            })],
+            user,
+            channel: interaction.channel.and_then(|c| c.name),
+            team: interaction.guild_id,
+        })
+        .map_err(|_| (Status::InternalServerError, "Internal error"))?;
+
+    // Shows "UDRB is thinking..." until the message is edited by the notifier.
+    Ok(Json(InteractionResponse {
+        type_: RESPONSE_DEFERRED_CHANNEL_MESSAGE,
+        data: None,
+    }))
+}
+
+// Edits the deferred response with the result.
+pub struct DiscordNotifier {
+    application_id: String,
+    // Interaction token, allows editing the response until it expires.
+    token: String,
+    received: Instant,
+}
+
+// Leaves out the token, so that it does not end up in logs.
+impl std::fmt::Debug for DiscordNotifier {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("DiscordNotifier")
+            .field("application_id", &self.application_id)
+            .field("received", &self.received)
+            .finish_non_exhaustive()
+    }
+}
+
+impl DiscordNotifier {
+    fn edit_original(&self, message: &MessageData) -> anyhow::Result<()> {
+        // Without a bot token there is no other way to reply, the result is
+        // only in the archive then.
+        anyhow::ensure!(
+            self.received.elapsed() < INTERACTION_TOKEN_TTL,
+            "Interaction token expired after {} minutes in the queue, the reply is lost",
+            self.received.elapsed().as_secs() / 60
+        );
+        let url = format!(
+            "{}/webhooks/{}/{}/messages/@original",
+            DISCORD_API, self.application_id, self.token
+        );
+        let client = notify::reply_client()?;
+        let response = client.patch(url).json(message).send()?;
+        if !response.status().is_success() {
+            return Err(anyhow::format_err!("Request failed: {:?}", response));
+        }
+        Ok(())
+    }
+}
+
+impl Notifier for DiscordNotifier {
+    fn on_success(&self, result: &RenderResult) -> anyhow::Result<()> {
+        let info = result.page_info.as_ref();
+        let mut footer = vec![];
+        if let Some(word_count) = result.word_count {
+            footer.push(format!(
+                "{} words, {} min read.",
+                word_count,
+                reading_time_minutes(word_count)
+            ));
+        }
+        let embed = Embed {
+            title: truncate(result.title.clone(), EMBED_TITLE_LENGTH),
+            url: result.orig_url.to_string(),
+            description: info
+                .and_then(|i| i.description.clone())
+                .map(|d| truncate(d, EMBED_DESCRIPTION_LENGTH)),
+            author: info.and_then(|i| i.author.clone()).map(|name| EmbedAuthor {
+                name,
+                icon_url: info.and_then(|i| i.icon.clone()),
+            }),
+            thumbnail: result
+                .thumbnail_url
+                .clone()
+                .or_else(|| info.and_then(|i| i.image.clone()))
+                .map(|url| EmbedImage { url }),
+            footer: Some(EmbedFooter {
+                text: footer.join(" "),
+            })
+            .filter(|f| !f.text.is_empty()),
+        };
+        self.edit_original(&MessageData {
+            content: result.user.as_ref().map(|u| format!("Shared by <@{}>.", u)),
+            embeds: vec![embed],
+            components: link_buttons(&[
+                (&result.pdf_url, "PDF"),
+                (&result.png_url, "Screenshot"),
+                (&result.reader_url, "Reader"),
+                (&result.markdown_url, "Markdown"),
+                (&result.mhtml_url, "Archive"),
+            ]),
+            ..Default::default()
+        })
+    }
+
+    fn on_failure(&self, _request: &RenderRequest, error: &RenderError) -> anyhow::Result<()> {
+        self.edit_original(&MessageData {
+            content: Some(format!("Error downloading: {}", error)),
+            ..Default::default()
+        })
+    }
+}
+
+pub fn routes() -> Vec<rocket::Route> {
+    rocket::routes![interactions]
+}
@@ -1,10 +1,21 @@
+mod api;
+mod archive;
+mod bulk;
 mod chrome;
+mod cli;
 mod config;
+mod discord;
+mod mattermost;
+mod notify;
 mod renderer;
 mod slack;
+mod web;
 
+use archive::Archive;
 use renderer::{RenderSender, Renderer};
+
 use slack::{SlackMessage, SlackRequestParser};
+use std::sync::Arc;
 
 use rocket::response::status::BadRequest;
 use rocket::serde::json::Json;
@@ -19,12 +30,14 @@ async fn slash(
     parser: SlackRequestParser,
     data: rocket::Data<'_>,
     sender: &rocket::State<RenderSender>,
+    config: &rocket::State<config::Config>,
+    archive: &rocket::State<Arc<Archive>>,
 ) -> Result<Json<SlackMessage>, BadRequest<&'static str>> {
     let request = parser
         .parse_slash(data)
         .await
         .map_err(|_| BadRequest("Couldn't parse or verify request"))?;
-    let (render_request, reply) = request.render_and_reply();
+    let (render_request, reply) = request.handle(config, archive);
     if let Some(request) = render_request {
         // Not async, but the queue is unbounded.
         sender
@@ -43,19 +56,80 @@ fn interactive() -> Result<(), ()> {
     Ok(())
 }
 
-#[rocket::launch]
-fn rocket() -> _ {
+// Not async, the renderer and the command line tools use blocking clients
+// which must not run inside the Rocket runtime.
+fn main() {
     env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
 
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let result = match args.as_slice() {
+        [] => rocket::execute(rocket().launch())
+            .map(|_| ())
+            .map_err(anyhow::Error::from),
+        ["check-config"] => cli::check_config(),
+        ["test-domain", url] => cli::test_domain(url),
+        ["render", args @ ..] => cli::render(args),
+        ["import", args @ ..] => cli::import(args),
+        _ => {
+            eprintln!("{}", cli::USAGE);
+            std::process::exit(2);
+        }
+    };
+    if let Err(e) = result {
+        eprintln!("Error: {:#}", e);
+        std::process::exit(1);
+    }
+}
+
+fn rocket() -> rocket::Rocket<rocket::Build> {
     let config = config::Config::from_env().expect("Error obtaining config");
     let output_dir = config.output_dir.clone();
 
-    let sender = Renderer::start(&config).expect("Failed to initialize renderer");
+    let archive =
+        Arc::new(Archive::open(config.archive_dir.clone()).expect("Failed to open archive"));
+    let sender = Renderer::start(&config, archive.clone()).expect("Failed to initialize renderer");
+    config.watch_domains();
+    let reload_config = config.clone();
 
     rocket::build()
         .manage(config)
         .manage(sender)
+        .manage(archive)
+        .manage(bulk::BulkImports::default())
         .mount("/", rocket::routes![index])
         .mount("/static", rocket::fs::FileServer::from(output_dir))
         .mount("/slack", rocket::routes![slash, interactive])
+        .mount("/mattermost", mattermost::routes())
+        .mount("/discord", discord::routes())
+        .mount("/archive", web::routes())
+        .register("/archive", web::catchers())
+        .mount("/api", api::routes())
+        .attach(api::Cors)
+        .attach(rocket::fairing::AdHoc::on_liftoff(
+            "Reload on SIGHUP",
+            |_| {
+                Box::pin(async move {
+                    rocket::tokio::spawn(reload_on_sighup(reload_config));
+                })
+            },
+        ))
+}
+
+async fn reload_on_sighup(config: config::Config) {
+    use rocket::tokio::signal::unix::{signal, SignalKind};
+    let mut hangups = match signal(SignalKind::hangup()) {
+        Ok(hangups) => hangups,
+        Err(e) => return log::error!("Failed to handle SIGHUP: {:?}", e),
+    };
+    while hangups.recv().await.is_some() {
+        let config = config.clone();
+        let result = rocket::tokio::task::spawn_blocking(move || config.reload_domains()).await;
+        if let Ok(Err(e)) = result {
+            log::error!(
+                "Failed to reload domain configs, keeping the old ones: {:?}",
+                e
+            );
+        }
+    }
 }
@@ -0,0 +1,241 @@
+// Mattermost slash command, mostly compatible with the Slack one but using
+// token verification and message attachments instead of Block Kit.
+
+use crate::archive::Archive;
+use crate::chrome::reading_time_minutes;
+use crate::config::Config;
+use crate::notify::{self, Notifier};
+use crate::renderer::{new_request_id, RenderError, RenderRequest, RenderResult, RenderSender};
+
+use std::sync::Arc;
+
+use log::error;
+use rocket::form::Form;
+use rocket::http::Status;
+use rocket::serde::json::Json;
+use rocket::State;
+use serde::Serialize;
+
+// Number of search results shown in the reply.
+const SEARCH_RESULT_COUNT: usize = 10;
+
+#[derive(Debug, rocket::FromForm)]
+pub struct SlashRequest {
+    token: String,
+    command: String,
+    text: String,
+    response_url: String,
+    user_name: Option<String>,
+    channel_name: Option<String>,
+    team_domain: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResponseType {
+    Ephemeral,
+    InChannel,
+}
+
+#[derive(Debug, Serialize, Default)]
+pub struct Attachment {
+    fallback: String,
+    title: String,
+    title_link: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    text: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    author_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    author_icon: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    thumb_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    footer: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Message {
+    response_type: ResponseType,
+    text: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    attachments: Vec<Attachment>,
+}
+
+fn text_message(text: String) -> Message {
+    Message {
+        response_type: ResponseType::Ephemeral,
+        text,
+        attachments: vec![],
+    }
+}
+
+// Markdown links to all the captured versions.
+fn links(result: &[(&Option<String>, &str)]) -> String {
+    result
+        .iter()
+        .filter_map(|(url, name)| url.as_ref().map(|url| format!("[{}]({})", name, url)))
+        .collect::<Vec<_>>()
+        .join(" · ")
+}
+
+fn search_reply(query: &str, archive: &Archive) -> Message {
+    let hits = archive.search(query, SEARCH_RESULT_COUNT);
+    if hits.is_empty() {
+        return text_message(format!("No captures found for _{}_.", query));
+    }
+    Message {
+        response_type: ResponseType::Ephemeral,
+        text: format!("Captures matching _{}_:", query),
+        attachments: hits
+            .into_iter()
+            .map(|hit| {
+                let capture = hit.capture;
+                let mut footer = capture.created.format("%Y-%m-%d").to_string();
+                if let Some(ref user) = capture.user {
+                    footer.push_str(&format!(", shared by @{}", user));
+                }
+                Attachment {
+                    fallback: capture.title.clone(),
+                    text: Some(format!(
+                        "{}\n{}",
+                        hit.snippet,
+                        links(&[
+                            (&capture.pdf_url, "PDF"),
+                            (&capture.png_url, "Screenshot"),
+                            (&capture.reader_url, "Reader"),
+                            (&capture.mhtml_url, "Archive"),
+                        ])
+                    )),
+                    title: capture.title,
+                    title_link: capture.orig_url.to_string(),
+                    footer: Some(footer),
+                    ..Default::default()
+                }
+            })
+            .collect(),
+    }
+}
+
+#[rocket::post("/slash", data = "<request>")]
+pub fn slash(
+    request: Form<SlashRequest>,
+    sender: &State<RenderSender>,
+    config: &State<Config>,
+    archive: &State<Arc<Archive>>,
+) -> Result<Json<Message>, (Status, &'static str)> {
+    let request = request.into_inner();
+    let expected = config
+        .mattermost
+        .token
+        .as_deref()
+        .ok_or((Status::Unauthorized, "Mattermost is not configured"))?;
+    if !constant_time_eq::constant_time_eq(expected.as_bytes(), request.token.as_bytes()) {
+        error!("Rejecting bad Mattermost token");
+        return Err((Status::Unauthorized, "Invalid token"));
+    }
+
+    let usage = text_message(
+        "Bad request. Usage: `/udrb http://...` or `/udrb search <query>`".to_string(),
+    );
+    if request.command != "/udrb" {
+        return Ok(Json(usage));
+    }
+    if let Some(query) = request.text.strip_prefix("search ") {
+        return Ok(Json(search_reply(query.trim(), archive)));
+    }
+    let url = match url::Url::parse(request.text.trim()) {
+        Ok(url) => url,
+        Err(_) => return Ok(Json(usage)),
+    };
+
+    sender
+        .render(RenderRequest {
+            id: new_request_id(&url),
+            url,
+            options: Default::default(),
+            notifiers: vec![Box::new(MattermostNotifier {
+                response_url: request.response_url,
+            })],
+            user: request.user_name,
+            channel: request.channel_name,
+            team: request.team_domain,
+        })
+        .map_err(|_| (Status::InternalServerError, "Internal error"))?;
+    Ok(Json(text_message(
+        "_Downloading, please wait..._".to_string(),
+    )))
+}
+
+// Posts the result as a reply to the slash command.
+#[derive(Debug)]
+pub struct MattermostNotifier {
+    pub response_url: String,
+}
+
+impl MattermostNotifier {
+    fn post(&self, message: &Message) -> anyhow::Result<()> {
+        let client = notify::reply_client()?;
+        let response = client.post(&self.response_url).json(message).send()?;
+        if !response.status().is_success() {
+            return Err(anyhow::format_err!("Request failed: {:?}", response));
+        }
+        Ok(())
+    }
+}
+
+impl Notifier for MattermostNotifier {
+    fn on_success(&self, result: &RenderResult) -> anyhow::Result<()> {
+        let info = result.page_info.as_ref();
+        let mut text = vec![];
+        if let Some(description) = info.and_then(|i| i.description.as_ref()) {
+            text.push(description.clone());
+        }
+        text.push(links(&[
+            (&Some(result.orig_url.to_string()), "Original"),
+            (&result.pdf_url, "PDF"),
+            (&result.png_url, "Screenshot"),
+            (&result.reader_url, "Reader"),
+            (&result.markdown_url, "Markdown"),
+            (&result.mhtml_url, "Archive (experimental)"),
+        ]));
+
+        let mut footer = vec![];
+        if let Some(word_count) = result.word_count {
+            footer.push(format!(
+                "{} words, {} min read.",
+                word_count,
+                reading_time_minutes(word_count)
+            ));
+        }
+        if let Some(ref user) = result.user {
+            footer.push(format!("Shared by @{}.", user));
+        }
+
+        self.post(&Message {
+            response_type: ResponseType::InChannel,
+            text: String::new(),
+            attachments: vec![Attachment {
+                fallback: result.title.clone(),
+                title: result.title.clone(),
+                title_link: result.orig_url.to_string(),
+                text: Some(text.join("\n\n")),
+                author_name: info.and_then(|i| i.author.clone()),
+                author_icon: info.and_then(|i| i.icon.clone()),
+                thumb_url: result
+                    .thumbnail_url
+                    .clone()
+                    .or_else(|| info.and_then(|i| i.image.clone())),
+                footer: Some(footer.join(" ")).filter(|f| !f.is_empty()),
+            }],
+        })
+    }
+
+    fn on_failure(&self, _request: &RenderRequest, error: &RenderError) -> anyhow::Result<()> {
+        self.post(&text_message(format!("Error downloading: {}", error)))
+    }
+}
+
+pub fn routes() -> Vec<rocket::Route> {
+    rocket::routes![slash]
+}
@@ -0,0 +1,176 @@
+// Email notifications via a local SMTP relay (plain SMTP without TLS or
+// authentication, e.g. postfix on the docker host).
+
+use super::Notifier;
+use crate::chrome::reading_time_minutes;
+use crate::config::SmtpConfig;
+use crate::renderer::{RenderError, RenderRequest, RenderResult};
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use anyhow::format_err;
+use base64::Engine;
+use log::error;
+
+const SMTP_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug)]
+pub struct EmailNotifier {
+    pub smtp: SmtpConfig,
+    pub to: String,
+}
+
+struct SmtpConnection {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+}
+
+impl SmtpConnection {
+    fn open(address: &str) -> anyhow::Result<SmtpConnection> {
+        let address = address
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| format_err!("Failed to resolve {}", address))?;
+        let stream = TcpStream::connect_timeout(&address, SMTP_TIMEOUT)?;
+        stream.set_read_timeout(Some(SMTP_TIMEOUT))?;
+        stream.set_write_timeout(Some(SMTP_TIMEOUT))?;
+        let mut connection = SmtpConnection {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+        };
+        connection.expect_reply(220)?;
+        Ok(connection)
+    }
+
+    // Reads a (possibly multi-line) reply and checks its code.
+    fn expect_reply(&mut self, code: u32) -> anyhow::Result<()> {
+        loop {
+            let mut line = String::new();
+            if self.reader.read_line(&mut line)? == 0 {
+                return Err(format_err!("SMTP connection closed"));
+            }
+            let reply_code = line.get(..3).and_then(|c| c.parse::<u32>().ok());
+            if reply_code != Some(code) {
+                return Err(format_err!("Unexpected SMTP reply: {}", line.trim_end()));
+            }
+            // "250-..." continues, "250 ..." is the last line.
+            if line.as_bytes().get(3) != Some(&b'-') {
+                return Ok(());
+            }
+        }
+    }
+
+    fn command(&mut self, command: &str, code: u32) -> anyhow::Result<()> {
+        self.writer
+            .write_all(format!("{}\r\n", command).as_bytes())?;
+        self.expect_reply(code)
+    }
+}
+
+// RFC 2047 encoding for non-ASCII header values.
+fn encode_header(value: &str) -> String {
+    if value.is_ascii() {
+        return value.to_string();
+    }
+    format!(
+        "=?UTF-8?B?{}?=",
+        base64::engine::general_purpose::STANDARD.encode(value)
+    )
+}
+
+// Line endings must be CRLF and lines starting with a dot need escaping.
+fn encode_body(body: &str) -> String {
+    body.lines()
+        .map(|line| {
+            if line.starts_with('.') {
+                format!(".{}\r\n", line)
+            } else {
+                format!("{}\r\n", line)
+            }
+        })
+        .collect()
+}
+
+// Sends the message in one SMTP conversation.
+fn deliver(address: &str, from: &str, to: &str, subject: &str, body: &str) -> anyhow::Result<()> {
+    let mut smtp = SmtpConnection::open(address)?;
+    smtp.command("HELO udrb", 250)?;
+    smtp.command(&format!("MAIL FROM:<{}>", from), 250)?;
+    smtp.command(&format!("RCPT TO:<{}>", to), 250)?;
+    smtp.command("DATA", 354)?;
+    let message = format!(
+        "From: {}\r\nTo: {}\r\nSubject: {}\r\nDate: {}\r\nMIME-Version: 1.0\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Transfer-Encoding: 8bit\r\n\r\n{}.",
+        from,
+        to,
+        encode_header(subject),
+        chrono::Utc::now().to_rfc2822(),
+        encode_body(body),
+    );
+    smtp.command(&message, 250)?;
+    smtp.command("QUIT", 221)?;
+    Ok(())
+}
+
+impl EmailNotifier {
+    // Sending happens in background, so that a slow SMTP server doesn't block
+    // the render queue. Failures are only logged.
+    fn send(&self, subject: &str, body: &str) -> anyhow::Result<()> {
+        let address = self
+            .smtp
+            .address
+            .clone()
+            .ok_or_else(|| format_err!("SMTP is not configured"))?;
+        // Header injection via the recipient.
+        anyhow::ensure!(
+            !self.to.contains(['\r', '\n', '<', '>']),
+            "Invalid recipient"
+        );
+
+        let (from, to) = (self.smtp.from.clone(), self.to.clone());
+        let (subject, body) = (subject.to_string(), body.to_string());
+        std::thread::spawn(move || {
+            if let Err(err) = deliver(&address, &from, &to, &subject, &body) {
+                error!("Email to {} failed: {:?}", to, err);
+            }
+        });
+        Ok(())
+    }
+}
+
+impl Notifier for EmailNotifier {
+    fn on_success(&self, result: &RenderResult) -> anyhow::Result<()> {
+        let mut body = vec![
+            result.title.clone(),
+            result.orig_url.to_string(),
+            String::new(),
+        ];
+        if let Some(word_count) = result.word_count {
+            body.push(format!(
+                "{} words, {} min read.\n",
+                word_count,
+                reading_time_minutes(word_count)
+            ));
+        }
+        for (name, url) in [
+            ("PDF", &result.pdf_url),
+            ("Screenshot", &result.png_url),
+            ("Reader", &result.reader_url),
+            ("Markdown", &result.markdown_url),
+            ("Archive", &result.mhtml_url),
+        ] {
+            if let Some(url) = url {
+                body.push(format!("{}: {}", name, url));
+            }
+        }
+        self.send(&format!("Captured: {}", result.title), &body.join("\n"))
+    }
+
+    fn on_failure(&self, request: &RenderRequest, error: &RenderError) -> anyhow::Result<()> {
+        self.send(
+            &format!("Capture failed: {}", request.url),
+            &format!("{}\n\nError: {}", request.url, error),
+        )
+    }
+}
@@ -0,0 +1,182 @@
+// Notifications about finished renders. Each request carries a list of
+// notifiers which the render loop calls once the request is done.
+
+mod email;
+pub use email::EmailNotifier;
+
+use crate::renderer::{RenderError, RenderRequest, RenderResult};
+
+use std::sync::Mutex;
+use std::time::Duration;
+
+use log::{error, warn};
+use rocket::tokio::sync::oneshot;
+use serde::Serialize;
+
+// Chat replies are sent from the render thread, a hanging API must not hold
+// up the queue.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(15);
+
+// Client for the chat notifiers.
+pub fn reply_client() -> reqwest::Result<reqwest::blocking::Client> {
+    reqwest::blocking::Client::builder()
+        .timeout(REPLY_TIMEOUT)
+        .build()
+}
+
+pub trait Notifier: Send + Sync + std::fmt::Debug {
+    fn on_success(&self, result: &RenderResult) -> anyhow::Result<()>;
+    fn on_failure(&self, request: &RenderRequest, error: &RenderError) -> anyhow::Result<()>;
+}
+
+// JSON body posted by the webhook notifier.
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Payload<'a> {
+    Success {
+        result: &'a RenderResult,
+    },
+    Failure {
+        id: &'a str,
+        url: &'a url::Url,
+        error: String,
+        user: Option<&'a str>,
+        channel: Option<&'a str>,
+        team: Option<&'a str>,
+    },
+}
+
+impl<'a> Payload<'a> {
+    pub fn failure(request: &'a RenderRequest, error: &RenderError) -> Payload<'a> {
+        Payload::Failure {
+            id: &request.id,
+            url: &request.url,
+            error: error.to_string(),
+            user: request.user.as_deref(),
+            channel: request.channel.as_deref(),
+            team: request.team.as_deref(),
+        }
+    }
+}
+
+// Delivery attempts and the delay before the first retry, doubled after each attempt.
+const WEBHOOK_ATTEMPTS: u32 = 5;
+const WEBHOOK_RETRY_DELAY: Duration = Duration::from_secs(2);
+
+// Signs the body the same way Slack signs its requests to us: HMAC-SHA256
+// of "v0:<timestamp>:<body>", sent as "v0=<hex>" with the timestamp.
+fn signature(secret: &str, timestamp: i64, body: &str) -> String {
+    let basestring = format!("v0:{}:{}", timestamp, body);
+    format!(
+        "v0={}",
+        hex::encode(hmac_sha256::HMAC::mac(basestring, secret))
+    )
+}
+
+// POSTs the result as JSON to the given URL. Delivery happens in background
+// with retries, so that a slow receiver doesn't block the render queue.
+pub struct WebhookNotifier {
+    pub url: String,
+    pub secret: Option<String>,
+}
+
+// Leaves out the secret, so that it does not end up in logs.
+impl std::fmt::Debug for WebhookNotifier {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("WebhookNotifier")
+            .field("url", &self.url)
+            .field("signed", &self.secret.is_some())
+            .finish()
+    }
+}
+
+fn deliver(url: &str, secret: Option<&str>, body: &str) -> anyhow::Result<()> {
+    let client = reqwest::blocking::Client::builder()
+        .timeout(Duration::from_secs(30))
+        .build()?;
+    let mut request = client
+        .post(url)
+        .header("Content-Type", "application/json")
+        .body(body.to_string());
+    if let Some(secret) = secret {
+        let timestamp = chrono::Utc::now().timestamp();
+        request = request
+            .header("X-Udrb-Request-Timestamp", timestamp.to_string())
+            .header("X-Udrb-Signature", signature(secret, timestamp, body));
+    }
+    let response = request.send()?;
+    if !response.status().is_success() {
+        return Err(anyhow::format_err!("Webhook failed: {:?}", response));
+    }
+    Ok(())
+}
+
+impl WebhookNotifier {
+    fn post(&self, payload: &Payload) -> anyhow::Result<()> {
+        let body = serde_json::to_string(payload)?;
+        let url = self.url.clone();
+        let secret = self.secret.clone();
+        std::thread::spawn(move || {
+            let mut delay = WEBHOOK_RETRY_DELAY;
+            for attempt in 1..=WEBHOOK_ATTEMPTS {
+                match deliver(&url, secret.as_deref(), &body) {
+                    Ok(()) => return,
+                    Err(err) => warn!("Webhook {} attempt {} failed: {:?}", url, attempt, err),
+                }
+                if attempt < WEBHOOK_ATTEMPTS {
+                    std::thread::sleep(delay);
+                    delay *= 2;
+                }
+            }
+            error!("Giving up on webhook {}", url);
+        });
+        Ok(())
+    }
+}
+
+impl Notifier for WebhookNotifier {
+    fn on_success(&self, result: &RenderResult) -> anyhow::Result<()> {
+        self.post(&Payload::Success { result })
+    }
+
+    fn on_failure(&self, request: &RenderRequest, error: &RenderError) -> anyhow::Result<()> {
+        self.post(&Payload::failure(request, error))
+    }
+}
+
+// Error message if the render failed.
+pub type Outcome = Result<(), String>;
+
+// Delivers the outcome to an in-process consumer, e.g. HTTP API request
+// waiting for the render to finish.
+#[derive(Debug)]
+pub struct ChannelNotifier(Mutex<Option<oneshot::Sender<Outcome>>>);
+
+impl ChannelNotifier {
+    pub fn new() -> (ChannelNotifier, oneshot::Receiver<Outcome>) {
+        let (sender, receiver) = oneshot::channel();
+        (ChannelNotifier(Mutex::new(Some(sender))), receiver)
+    }
+
+    fn send(&self, outcome: Outcome) -> anyhow::Result<()> {
+        let sender = self
+            .0
+            .lock()
+            .unwrap()
+            .take()
+            .ok_or_else(|| anyhow::format_err!("Already notified"))?;
+        // The receiver might have given up waiting already, that is fine.
+        let _ = sender.send(outcome);
+        Ok(())
+    }
+}
+
+impl Notifier for ChannelNotifier {
+    fn on_success(&self, _result: &RenderResult) -> anyhow::Result<()> {
+        self.send(Ok(()))
+    }
+
+    fn on_failure(&self, _request: &RenderRequest, error: &RenderError) -> anyhow::Result<()> {
+        self.send(Err(error.to_string()))
+    }
+}
@@ -1,35 +1,129 @@
-use crate::chrome::{ChromeDriver, PageInfo};
-use crate::config::Config;
-use crate::slack;
+use crate::archive::Archive;
+use crate::chrome::{
+    save_thumbnail, ChromeDriver, DeviceProfile, LoginError, PageInfo, PdfOptions, RenderCss,
+    ScreenshotOptions, ScriptError,
+};
+use crate::config::{Config, DomainConfig};
+use crate::notify::{Notifier, WebhookNotifier};
 
+use std::collections::HashMap;
 use std::sync::mpsc;
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use log::warn;
 use log::{error, info};
+use sha3::Digest;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    Pdf,
+    Png,
+    Mhtml,
+}
+
+#[derive(Clone, Debug, Default, serde::Deserialize)]
+pub struct RenderOptions {
+    // Formats to capture, all of them if empty.
+    #[serde(default)]
+    pub formats: Vec<Format>,
+    // Overrides the PDF options of the domain config.
+    #[serde(default)]
+    pub pdf: PdfOptions,
+    #[serde(default)]
+    pub screenshot: ScreenshotOptions,
+    // Overrides the device of the domain config.
+    #[serde(default)]
+    pub device: Option<DeviceProfile>,
+}
+
+impl RenderOptions {
+    fn wants(&self, format: Format) -> bool {
+        self.formats.is_empty() || self.formats.contains(&format)
+    }
+}
 
 #[derive(Debug)]
 pub struct RenderRequest {
+    // Unique id, also used for the archived capture.
+    pub id: String,
     pub url: url::Url,
-    pub slack_callback: String,
+    pub options: RenderOptions,
+    // Called when the request is finished.
+    pub notifiers: Vec<Box<dyn Notifier>>,
     pub user: Option<String>,
     pub channel: Option<String>,
     pub team: Option<String>,
 }
 
+// Generates sortable, unique enough id for a new request.
+pub fn new_request_id(url: &url::Url) -> String {
+    let now = chrono::Utc::now();
+    let mut hasher = sha3::Sha3_256::new();
+    hasher.update(url.as_str());
+    hasher.update(now.timestamp_nanos_opt().unwrap_or_default().to_le_bytes());
+    let hash = hex::encode(hasher.finalize());
+    format!("{}-{}", now.format("%Y%m%d%H%M%S"), &hash[..8])
+}
+
+// Status of requests which are not successfully finished yet. Successful
+// requests are removed, they can be found in the archive.
+#[derive(Clone, Debug)]
+pub enum RenderStatus {
+    Queued,
+    Rendering,
+    Failed(String),
+}
+
+// Failed requests are forgotten after this, the status map would grow forever otherwise.
+const FAILED_STATUS_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+// With the time of the last update.
+type RenderStatuses = Arc<Mutex<HashMap<String, (RenderStatus, Instant)>>>;
+
 // Send part of the render queue.
-pub struct RenderSender(Mutex<mpsc::Sender<RenderRequest>>);
+pub struct RenderSender {
+    sender: Mutex<mpsc::Sender<RenderRequest>>,
+    statuses: RenderStatuses,
+}
 
 impl RenderSender {
     // Enqueues the request.
     pub fn render(&self, request: RenderRequest) -> anyhow::Result<()> {
-        Ok(self.0.lock().unwrap().send(request)?)
+        self.statuses
+            .lock()
+            .unwrap()
+            .insert(request.id.clone(), (RenderStatus::Queued, Instant::now()));
+        Ok(self.sender.lock().unwrap().send(request)?)
+    }
+
+    pub fn status(&self, id: &str) -> Option<RenderStatus> {
+        self.statuses
+            .lock()
+            .unwrap()
+            .get(id)
+            .map(|(status, _)| status.clone())
+    }
+}
+
+// For enqueueing from other threads, e.g. bulk imports.
+impl Clone for RenderSender {
+    fn clone(&self) -> Self {
+        RenderSender {
+            sender: Mutex::new(self.sender.lock().unwrap().clone()),
+            statuses: self.statuses.clone(),
+        }
     }
 }
 
 pub struct Renderer {
     config: Config,
     chrome: ChromeDriver,
+    archive: Arc<Archive>,
+    // Webhooks from the config, called for every request.
+    webhooks: Vec<WebhookNotifier>,
+    statuses: RenderStatuses,
     receiver: mpsc::Receiver<RenderRequest>,
 }
 
@@ -38,6 +132,12 @@ pub enum RenderError {
     InternalError(anyhow::Error),
     InvalidUrlError,
     UnsupportedDomain,
+    // Login or render script threw, with the JavaScript error message.
+    ScriptError(String),
+    // One of the domain login_steps failed.
+    LoginFailed(String),
+    // The domain render_check didn't pass, e.g. paywall or login wall.
+    CheckFailed,
 }
 
 impl std::fmt::Display for RenderError {
@@ -47,6 +147,12 @@ impl std::fmt::Display for RenderError {
             InternalError(e) => write!(f, "Internal error ({:?})", e),
             InvalidUrlError => write!(f, "URL is not valid."),
             UnsupportedDomain => write!(f, "Domain is not supported."),
+            ScriptError(e) => write!(f, "Script failed ({}).", e),
+            LoginFailed(e) => write!(f, "Login failed at {}.", e),
+            CheckFailed => write!(
+                f,
+                "Page is missing the expected content (paywall or login required?)."
+            ),
         }
     }
 }
@@ -56,15 +162,51 @@ fn wrap_internal_error(e: anyhow::Error) -> RenderError {
     RenderError::InternalError(e)
 }
 
-#[derive(Debug)]
+// Exceptions thrown by scripts are reported as such, everything else is internal.
+fn wrap_script_error(e: anyhow::Error) -> RenderError {
+    match e.downcast::<ScriptError>() {
+        Ok(ScriptError(message)) => {
+            log::warn!("Script error: {}", message);
+            RenderError::ScriptError(message)
+        }
+        Err(e) => wrap_internal_error(e),
+    }
+}
+
+fn wrap_login_error(e: anyhow::Error) -> RenderError {
+    match e.downcast::<LoginError>() {
+        Ok(e) => {
+            log::warn!("Login failed: {}", e);
+            RenderError::LoginFailed(e.to_string())
+        }
+        Err(e) => wrap_internal_error(e),
+    }
+}
+
+#[derive(Debug, serde::Serialize)]
 pub struct RenderResult {
+    // Id of the request.
+    pub id: String,
     // Title of the document.
     pub title: String,
     // URLs to the original document and rendered versions.
     pub orig_url: url::Url,
     pub pdf_url: Option<String>,
+    // Screenshot, can also be JPEG or WebP depending on the options.
     pub png_url: Option<String>,
+    // All parts if the screenshot was split into tiles, png_url is the first one.
+    pub screenshot_tiles: Vec<String>,
+    // Downscaled top of the screenshot.
+    pub thumbnail_url: Option<String>,
     pub mhtml_url: Option<String>,
+    // Reader view of the article extracted from MHTML.
+    pub reader_url: Option<String>,
+    pub markdown_url: Option<String>,
+    pub text_url: Option<String>,
+    pub word_count: Option<usize>,
+    // Text of the article (or whole page) for the search index.
+    #[serde(skip)]
+    pub text: Option<String>,
     // Additional page info extracted from MHTML.
     pub page_info: Option<PageInfo>,
     // User, channel and team names (from Slack).
@@ -73,7 +215,57 @@ pub struct RenderResult {
     pub team: Option<String>,
 }
 
-fn handle_request_once(
+// Human readable steps handle_request_once takes for the domain, keep in sync.
+pub fn render_plan(domain_config: &DomainConfig, options: &RenderOptions) -> Vec<String> {
+    let mut steps = vec![];
+    let device = options.device.as_ref().or(domain_config.device.as_ref());
+    match device.map(DeviceProfile::device) {
+        Some(device) => steps.push(format!("Emulate device {:?}", device)),
+        None => steps.push("Use the browser default device".to_string()),
+    }
+    if !domain_config.filter_lists.is_empty() {
+        steps.push(format!("Block requests {:?}", domain_config.filter_lists));
+    }
+    if let Some(ref login_page) = domain_config.login_page {
+        steps.push(format!("Open login page {}", login_page));
+    }
+    if domain_config.login_script.is_some() {
+        steps.push("Run login script".to_string());
+    }
+    for (i, step) in domain_config.login_steps.iter().enumerate() {
+        steps.push(format!("Login step {}: {}", i + 1, step.action));
+    }
+    if domain_config.render_css.is_some() {
+        steps.push("Inject render CSS".to_string());
+    }
+    steps.push("Open the URL".to_string());
+    if domain_config.render_script.is_some() {
+        steps.push("Run render script".to_string());
+    }
+    let cleanup = &domain_config.cleanup;
+    if cleanup.is_active() {
+        steps.push(format!("Remove overlays {:?}", cleanup));
+    }
+    if let Some(ref scroll) = domain_config.scroll {
+        steps.push(format!("Scroll to the bottom {:?}", scroll));
+    }
+    if let Some(ref render_check) = domain_config.render_check {
+        steps.push(format!("Check {}", render_check));
+    }
+    if options.wants(Format::Pdf) {
+        steps.push(format!("Save PDF {:?}", options.pdf.or(&domain_config.pdf)));
+    }
+    if options.wants(Format::Png) {
+        let screenshot = options.screenshot.or(&domain_config.screenshot);
+        steps.push(format!("Save screenshot and thumbnail {:?}", screenshot));
+    }
+    if options.wants(Format::Mhtml) {
+        steps.push("Save MHTML snapshot and reader view".to_string());
+    }
+    steps
+}
+
+pub fn handle_request_once(
     req: &RenderRequest,
     config: &Config,
     chrome: &mut ChromeDriver,
@@ -81,24 +273,46 @@ fn handle_request_once(
     if req.url.scheme() != "http" && req.url.scheme() != "https" {
         return Err(RenderError::InvalidUrlError);
     }
-    let host = req.url.domain().ok_or(RenderError::InvalidUrlError)?;
+    if !req.url.has_host() {
+        return Err(RenderError::InvalidUrlError);
+    }
     let domain_config = config
-        .domains
-        .iter()
-        .find(|dc| dc.host.is_match(host))
+        .find_domain(&req.url)
         .ok_or(RenderError::UnsupportedDomain)?;
+    info!("Using domain config {}", domain_config.name);
+
+    // Before any navigation, sites pick the mobile version based on the user agent.
+    let device = req
+        .options
+        .device
+        .as_ref()
+        .or(domain_config.device.as_ref())
+        .map(DeviceProfile::device);
+    let viewport = device.as_ref().map(|d| (d.width, d.height));
+    chrome.emulate_device(device).map_err(wrap_internal_error)?;
+    chrome
+        .set_blocking(domain_config.filter_lists.clone(), &req.url)
+        .map_err(wrap_internal_error)?;
 
     // Navigate to login page and run login script if specified.
+    // The stylesheet of the previous request must not apply to it.
+    chrome.set_stylesheet(None).map_err(wrap_internal_error)?;
     if let Some(ref login_page) = domain_config.login_page {
         chrome.navigate(login_page).map_err(wrap_internal_error)?;
     }
     if let Some(ref login_script) = domain_config.login_script {
+        chrome.run_script(login_script).map_err(wrap_script_error)?;
+    }
+    if !domain_config.login_steps.is_empty() {
         chrome
-            .run_script(login_script)
-            .map_err(wrap_internal_error)?;
+            .run_login_steps(&domain_config.login_steps, domain_config.totp.as_ref())
+            .map_err(wrap_login_error)?;
     }
 
     // Navigate to the requested content.
+    chrome
+        .set_stylesheet(domain_config.render_css.as_ref().map(RenderCss::css))
+        .map_err(wrap_internal_error)?;
     chrome
         .navigate(req.url.as_str())
         .map_err(wrap_internal_error)?;
@@ -106,40 +320,94 @@ fn handle_request_once(
     if let Some(ref render_script) = domain_config.render_script {
         chrome
             .run_script(render_script)
+            .map_err(wrap_script_error)?;
+    }
+
+    // Failing to clean up is not worth failing the whole capture.
+    if let Err(e) = chrome.remove_overlays(&domain_config.cleanup) {
+        warn!("Overlay cleanup failed: {:?}", e);
+    }
+
+    if let Some(ref scroll) = domain_config.scroll {
+        chrome
+            .scroll_to_bottom(scroll)
             .map_err(wrap_internal_error)?;
     }
 
+    if let Some(ref render_check) = domain_config.render_check {
+        if !chrome.check_page(render_check).map_err(wrap_script_error)? {
+            warn!("Render check failed: {}", render_check);
+            return Err(RenderError::CheckFailed);
+        }
+    }
+
     let title = chrome.get_title().map_err(wrap_internal_error)?;
 
     // All these are optional and ignored when they fail.
     let to_url = |filename: &str| format!("{}/static/{}", config.hostname, filename);
-    let pdf_file = chrome
-        .save_pdf(config.output_dir.as_path())
-        .map_err(wrap_internal_error);
-    let png_file = chrome
-        .save_screenshot(config.output_dir.as_path())
-        .map_err(wrap_internal_error);
-    let mhtml_result = chrome
-        .save_mhtml(config.output_dir.as_path())
-        .map_err(wrap_internal_error);
+    let options = &req.options;
+    let pdf_file = options.wants(Format::Pdf).then(|| {
+        let pdf_options = options.pdf.or(&domain_config.pdf);
+        chrome
+            .save_pdf(config.output_dir.as_path(), &pdf_options, req.url.as_str())
+            .map_err(wrap_internal_error)
+    });
+    let screenshot_files = options.wants(Format::Png).then(|| {
+        let screenshot_options = options.screenshot.or(&domain_config.screenshot);
+        chrome
+            .save_screenshot(config.output_dir.as_path(), &screenshot_options)
+            .map_err(wrap_internal_error)
+    });
+    let snapshot = options.wants(Format::Mhtml).then(|| {
+        chrome
+            .save_mhtml(config.output_dir.as_path())
+            .map_err(wrap_internal_error)
+    });
+    if !domain_config.filter_lists.is_empty() {
+        info!("Blocked {} requests", chrome.blocked_requests());
+    }
 
     // Require that at least PDF of PNG is available (MHTML is experimental, it alone
-    // is not enough to consider this a success).
-    if pdf_file.is_err() && png_file.is_err() {
+    // is not enough to consider this a success), unless only MHTML was requested.
+    let success = if pdf_file.is_some() || screenshot_files.is_some() {
+        matches!(pdf_file, Some(Ok(_))) || matches!(screenshot_files, Some(Ok(_)))
+    } else {
+        matches!(snapshot, Some(Ok(_)))
+    };
+    if !success {
         return Err(RenderError::InternalError(anyhow::anyhow!(
-            "Failed to capture either PDF or screenshot"
+            "Failed to capture any of the requested formats"
         )));
     }
+    let pdf_file = pdf_file.and_then(Result::ok);
+    let screenshot_files = screenshot_files.and_then(Result::ok).unwrap_or_default();
+    let thumbnail_file = screenshot_files.first().and_then(|file| {
+        save_thumbnail(config.output_dir.as_path(), file, viewport)
+            .map_err(|e| warn!("Failed to create thumbnail: {:?}", e))
+            .ok()
+    });
+    let snapshot = snapshot.and_then(Result::ok);
+    let article = snapshot.as_ref().and_then(|s| s.article.as_ref());
+
     Ok(RenderResult {
+        id: req.id.clone(),
         title,
         orig_url: req.url.clone(),
-        pdf_url: pdf_file.as_deref().map(to_url).ok(),
-        png_url: png_file.as_deref().map(to_url).ok(),
-        mhtml_url: mhtml_result
-            .as_ref()
-            .map(|(mhtml_file, _)| to_url(mhtml_file))
-            .ok(),
-        page_info: mhtml_result.map(|(_, info)| info).ok().flatten(),
+        pdf_url: pdf_file.as_deref().map(to_url),
+        png_url: screenshot_files.first().map(|f| to_url(f)),
+        thumbnail_url: thumbnail_file.as_deref().map(to_url),
+        screenshot_tiles: if screenshot_files.len() > 1 {
+            screenshot_files.iter().map(|f| to_url(f)).collect()
+        } else {
+            vec![]
+        },
+        mhtml_url: snapshot.as_ref().map(|s| to_url(&s.index_file)),
+        reader_url: article.map(|a| to_url(&a.html_file)),
+        markdown_url: article.map(|a| to_url(&a.markdown_file)),
+        text_url: article.map(|a| to_url(&a.text_file)),
+        word_count: article.map(|a| a.word_count),
+        text: snapshot.as_ref().and_then(|s| s.text.clone()),
+        page_info: snapshot.and_then(|s| s.page_info),
         user: req.user.clone(),
         channel: req.channel.clone(),
         team: req.team.clone(),
@@ -172,9 +440,10 @@ fn handle_request(
 }
 
 impl Renderer {
-    pub fn start(config: &Config) -> anyhow::Result<RenderSender> {
+    pub fn start(config: &Config, archive: Arc<Archive>) -> anyhow::Result<RenderSender> {
         // Render queue channel.
         let (sender, receiver) = mpsc::channel();
+        let statuses = RenderStatuses::default();
 
         // Initialize Chrome driver.
         let chrome = ChromeDriver::new(&config.chrome_address, &config.chrome_kill_address)?;
@@ -182,6 +451,17 @@ impl Renderer {
         let mut renderer = Renderer {
             config: config.clone(),
             chrome,
+            archive,
+            webhooks: config
+                .webhooks
+                .urls
+                .iter()
+                .map(|url| WebhookNotifier {
+                    url: url.clone(),
+                    secret: config.webhooks.secret.clone(),
+                })
+                .collect(),
+            statuses: statuses.clone(),
             receiver,
         };
 
@@ -189,7 +469,10 @@ impl Renderer {
         std::thread::spawn(move || renderer.render_loop());
 
         // Return the sender for queueing RenderRequest.
-        Ok(RenderSender(Mutex::new(sender)))
+        Ok(RenderSender {
+            sender: Mutex::new(sender),
+            statuses,
+        })
     }
 
     fn render_loop(&mut self) {
@@ -202,21 +485,51 @@ impl Renderer {
                 request.team.as_deref().unwrap_or("?"),
                 request.url
             );
+            self.set_status(&request.id, Some(RenderStatus::Rendering));
             let result = handle_request(&request, &self.config, &mut self.chrome);
 
-            let slack_result = match result {
-                Ok(result) => {
-                    info!("Request success: {result:?}");
-                    slack::post_success(&request.slack_callback, &result)
+            match result {
+                Ok(ref result) => {
+                    // Not logging the whole result, the text can be huge.
+                    info!("Request success: {:?} ({})", result.title, result.orig_url);
+                    match self.archive.add(result) {
+                        Ok(_) => self.set_status(&request.id, None),
+                        Err(err) => {
+                            error!("Archiving failed: {err:?}");
+                            self.set_status(
+                                &request.id,
+                                Some(RenderStatus::Failed(format!("Archiving failed: {}", err))),
+                            );
+                        }
+                    }
                 }
-                Err(err) => {
+                Err(ref err) => {
                     error!("Request failed: {err:?}");
-                    slack::post_failure(&request.slack_callback, &err)
+                    self.set_status(&request.id, Some(RenderStatus::Failed(err.to_string())));
                 }
             };
-            if let Err(err) = slack_result {
-                error!("Slack posting failed: {err:?}");
+
+            let webhooks = self.webhooks.iter().map(|w| w as &dyn Notifier);
+            for notifier in request.notifiers.iter().map(Box::as_ref).chain(webhooks) {
+                let notify_result = match result {
+                    Ok(ref result) => notifier.on_success(result),
+                    Err(ref err) => notifier.on_failure(&request, err),
+                };
+                if let Err(err) = notify_result {
+                    error!("Notification failed ({notifier:?}): {err:?}");
+                }
             }
         }
     }
+
+    fn set_status(&self, id: &str, status: Option<RenderStatus>) {
+        let mut statuses = self.statuses.lock().unwrap();
+        match status {
+            Some(status) => statuses.insert(id.to_string(), (status, Instant::now())),
+            None => statuses.remove(id),
+        };
+        statuses.retain(|_, (status, updated)| {
+            !matches!(status, RenderStatus::Failed(_)) || updated.elapsed() < FAILED_STATUS_TTL
+        });
+    }
 }
@@ -1,5 +1,8 @@
+use crate::archive::Archive;
+use crate::chrome::reading_time_minutes;
 use crate::config::{Config, SlackConfig};
-use crate::renderer::{RenderError, RenderRequest, RenderResult};
+use crate::notify::{self, Notifier};
+use crate::renderer::{new_request_id, RenderError, RenderRequest, RenderResult};
 
 use chrono::{TimeZone, Utc};
 use log::error;
@@ -21,25 +24,131 @@ pub struct SlashRequest {
     team_domain: Option<String>,
 }
 
-impl SlashRequest {
-    pub fn render_and_reply(self) -> (Option<RenderRequest>, SlackMessage) {
-        let usage = SlackMessage {
-            response_type: SlackResponseType::Ephemeral,
-            blocks: vec![SlackBlock {
-                type_: "context".to_string(),
-                elements: vec![SlackBlockElement {
-                    type_: "mrkdwn".to_string(),
-                    text: Some("Bad request. Usage: `/udrb http://...`".to_string()),
-                    ..Default::default()
-                }],
+// Number of search results shown in Slack, the web UI shows more.
+const SEARCH_RESULT_COUNT: usize = 10;
+
+fn context_message(text: String) -> SlackMessage {
+    SlackMessage {
+        response_type: SlackResponseType::Ephemeral,
+        blocks: vec![SlackBlock {
+            type_: "context".to_string(),
+            elements: vec![SlackBlockElement {
+                type_: "mrkdwn".to_string(),
+                text: Some(text),
                 ..Default::default()
             }],
-        };
+            ..Default::default()
+        }],
+    }
+}
+
+// Slack requires escaping of these three characters in mrkdwn text.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn search_reply(
+    query: &str,
+    team: Option<&str>,
+    config: &Config,
+    archive: &Archive,
+) -> SlackMessage {
+    let hits = archive.search(query, SEARCH_RESULT_COUNT);
+    if hits.is_empty() {
+        return context_message(format!("No captures found for _{}_.", escape(query)));
+    }
+
+    // The archive pages can be disabled, the hits below are enough then.
+    let heading = if config.archive.is_enabled() {
+        let search_url = format!(
+            "{}/archive/search?{}",
+            config.hostname,
+            url::form_urlencoded::Serializer::new(String::new())
+                .append_pair("q", query)
+                .finish()
+        );
+        format!(
+            "Captures matching _{}_ (<{}|all results>):",
+            escape(query),
+            search_url
+        )
+    } else {
+        format!("Captures matching _{}_:", escape(query))
+    };
+    let mut blocks = vec![SlackBlock {
+        type_: "context".to_string(),
+        elements: vec![SlackBlockElement {
+            type_: "mrkdwn".to_string(),
+            text: Some(heading),
+            ..Default::default()
+        }],
+        ..Default::default()
+    }];
+    for hit in hits {
+        let capture = hit.capture;
+        let mut details = vec![capture.created.format("%Y-%m-%d").to_string()];
+        if let Some(ref user) = capture.user {
+            // Only captures from this Slack team have a Slack user id, others
+            // (API, imports, other chats) have a name.
+            if team.is_some() && capture.team.as_deref() == team {
+                details.push(format!("shared by <@{}>", user));
+            } else {
+                details.push(format!("shared by {}", escape(user)));
+            }
+        }
+        let links: Vec<_> = [
+            (&capture.pdf_url, "PDF"),
+            (&capture.png_url, "Screenshot"),
+            (&capture.reader_url, "Reader"),
+            (&capture.mhtml_url, "Archive"),
+        ]
+        .into_iter()
+        .filter_map(|(url, name)| url.as_ref().map(|url| format!("<{}|{}>", url, name)))
+        .collect();
+        blocks.push(SlackBlock {
+            type_: "section".to_string(),
+            text: Some(SlackTextBlock {
+                type_: "mrkdwn".to_string(),
+                text: format!(
+                    "*<{}|{}>*\n{}\n{}\n_{}_",
+                    capture.orig_url,
+                    escape(&capture.title),
+                    details.join(", "),
+                    links.join(" · "),
+                    escape(&hit.snippet)
+                ),
+            }),
+            ..Default::default()
+        });
+    }
+    SlackMessage {
+        response_type: SlackResponseType::Ephemeral,
+        blocks,
+    }
+}
+
+impl SlashRequest {
+    // Returns the request to render (if any) and the immediate reply.
+    pub fn handle(
+        self,
+        config: &Config,
+        archive: &Archive,
+    ) -> (Option<RenderRequest>, SlackMessage) {
+        let usage = context_message(
+            "Bad request. Usage: `/udrb http://...` or `/udrb search <query>`".to_string(),
+        );
 
         if self.command != "/udrb" {
             return (None, usage);
         }
 
+        if let Some(query) = self.text.strip_prefix("search ") {
+            let team = self.team_domain.as_deref();
+            return (None, search_reply(query.trim(), team, config, archive));
+        }
+
         let url = match url::Url::parse(&self.text) {
             Ok(url) => url,
             Err(_) => {
@@ -49,24 +158,22 @@ impl SlashRequest {
 
         (
             Some(RenderRequest {
+                id: new_request_id(&url),
                 url,
-                slack_callback: self.response_url,
+                options: Default::default(),
+                notifiers: vec![Box::new(SlackNotifier {
+                    response_url: self.response_url,
+                    user_id: self.user_id.clone(),
+                    archive_url: config
+                        .archive
+                        .is_enabled()
+                        .then(|| format!("{}/archive", config.hostname)),
+                })],
                 user: self.user_id,
                 channel: self.channel_name,
                 team: self.team_domain,
             }),
-            SlackMessage {
-                response_type: SlackResponseType::Ephemeral,
-                blocks: vec![SlackBlock {
-                    type_: "context".to_string(),
-                    elements: vec![SlackBlockElement {
-                        type_: "mrkdwn".to_string(),
-                        text: Some("_Downloading, please wait..._".to_string()),
-                        ..Default::default()
-                    }],
-                    ..Default::default()
-                }],
-            },
+            context_message("_Downloading, please wait..._".to_string()),
         )
     }
 }
@@ -91,6 +198,12 @@ pub struct SlackBlock {
 
     #[serde(skip_serializing_if = "Option::is_none")]
     accessory: Option<SlackAccessory>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    image_url: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    alt_text: Option<String>,
 }
 
 #[derive(Debug, Serialize, Default)]
@@ -236,8 +349,28 @@ impl SlackRequestParser {
     }
 }
 
+// Posts the result as a reply to the slash command.
+#[derive(Debug)]
+pub struct SlackNotifier {
+    pub response_url: String,
+    // Slack user who asked for the capture, mentioned in the reply.
+    pub user_id: Option<String>,
+    // Base URL of the archive pages, None if they are disabled.
+    pub archive_url: Option<String>,
+}
+
+impl Notifier for SlackNotifier {
+    fn on_success(&self, result: &RenderResult) -> anyhow::Result<()> {
+        post_success(self, result)
+    }
+
+    fn on_failure(&self, _request: &RenderRequest, error: &RenderError) -> anyhow::Result<()> {
+        post_failure(&self.response_url, error)
+    }
+}
+
 fn post_slack_message(callback: &str, message: SlackMessage) -> anyhow::Result<()> {
-    let client = reqwest::blocking::Client::new();
+    let client = notify::reply_client()?;
     println!("{}", json::to_string(&message).unwrap());
     let response = client.post(callback).json(&message).send()?;
     if !response.status().is_success() {
@@ -246,7 +379,8 @@ fn post_slack_message(callback: &str, message: SlackMessage) -> anyhow::Result<(
     Ok(())
 }
 
-pub fn post_success(callback: &str, result: &RenderResult) -> anyhow::Result<()> {
+fn post_success(notifier: &SlackNotifier, result: &RenderResult) -> anyhow::Result<()> {
+    let archive_url = notifier.archive_url.as_deref();
     let mut response_blocks = Vec::new();
 
     // Header with the page title.
@@ -259,8 +393,7 @@ pub fn post_success(callback: &str, result: &RenderResult) -> anyhow::Result<()>
         ..Default::default()
     });
     // TODO: Maybe extract title from page, not <title>?
-    // TODO: Extract whole article without ads/menus etc ("reader view"),
-    //       use that to show the length / reading time, TTS narration, etc.
+    // TODO: Use the reader view for TTS narration, etc.
 
     // Page favicon and user who requested it.
     let mut favicon_and_user = SlackBlock {
@@ -285,10 +418,21 @@ pub fn post_success(callback: &str, result: &RenderResult) -> anyhow::Result<()>
             });
         }
     }
-    if let Some(ref user) = result.user {
+    if let Some(word_count) = result.word_count {
+        favicon_and_user.elements.push(SlackBlockElement {
+            type_: "mrkdwn".to_string(),
+            text: Some(format!(
+                "{} words, {} min read.",
+                word_count,
+                reading_time_minutes(word_count)
+            )),
+            ..Default::default()
+        });
+    }
+    if let Some(ref user_id) = notifier.user_id {
         favicon_and_user.elements.push(SlackBlockElement {
             type_: "mrkdwn".to_string(),
-            text: Some(format!("Shared by <@{}>.", user)),
+            text: Some(format!("Shared by <@{}>.", user_id)),
             ..Default::default()
         });
     }
@@ -318,6 +462,16 @@ pub fn post_success(callback: &str, result: &RenderResult) -> anyhow::Result<()>
         }
     }
 
+    // Preview of the captured page.
+    if let Some(ref thumbnail_url) = result.thumbnail_url {
+        response_blocks.push(SlackBlock {
+            type_: "image".to_string(),
+            image_url: Some(thumbnail_url.to_string()),
+            alt_text: Some("screenshot preview".to_string()),
+            ..Default::default()
+        });
+    }
+
     // Buttons with links to all the versions.
     let mut buttons_block = SlackBlock {
         type_: "actions".to_string(),
@@ -347,14 +501,63 @@ pub fn post_success(callback: &str, result: &RenderResult) -> anyhow::Result<()>
         });
     }
     if let Some(ref png_url) = result.png_url {
+        // Tiled screenshots are shown together on the archive page, without
+        // it only the first part is linked.
+        let (text, url) = match (result.screenshot_tiles.len(), archive_url) {
+            (0, _) => (":camera: Screenshot".to_string(), png_url.to_string()),
+            (tiles, Some(archive_url)) => (
+                format!(":camera: Screenshot ({} parts)", tiles),
+                format!("{}/{}", archive_url, result.id),
+            ),
+            (tiles, None) => (
+                format!(":camera: Screenshot (part 1 of {})", tiles),
+                png_url.to_string(),
+            ),
+        };
+        buttons_block.elements.push(SlackBlockElement {
+            type_: "button".to_string(),
+            button_text: Some(SlackButtonText {
+                type_: "plain_text".to_string(),
+                text,
+                emoji: true,
+            }),
+            url: Some(url),
+            ..Default::default()
+        });
+    }
+    if let Some(ref reader_url) = result.reader_url {
+        buttons_block.elements.push(SlackBlockElement {
+            type_: "button".to_string(),
+            button_text: Some(SlackButtonText {
+                type_: "plain_text".to_string(),
+                text: ":newspaper: Reader".to_string(),
+                emoji: true,
+            }),
+            url: Some(reader_url.to_string()),
+            ..Default::default()
+        });
+    }
+    if let Some(ref markdown_url) = result.markdown_url {
+        buttons_block.elements.push(SlackBlockElement {
+            type_: "button".to_string(),
+            button_text: Some(SlackButtonText {
+                type_: "plain_text".to_string(),
+                text: ":memo: Markdown".to_string(),
+                emoji: true,
+            }),
+            url: Some(markdown_url.to_string()),
+            ..Default::default()
+        });
+    }
+    if let Some(ref text_url) = result.text_url {
         buttons_block.elements.push(SlackBlockElement {
             type_: "button".to_string(),
             button_text: Some(SlackButtonText {
                 type_: "plain_text".to_string(),
-                text: ":camera: Screenshot".to_string(),
+                text: ":page_facing_up: Text".to_string(),
                 emoji: true,
             }),
-            url: Some(png_url.to_string()),
+            url: Some(text_url.to_string()),
             ..Default::default()
         });
     }
@@ -373,7 +576,7 @@ pub fn post_success(callback: &str, result: &RenderResult) -> anyhow::Result<()>
     response_blocks.push(buttons_block);
 
     post_slack_message(
-        callback,
+        &notifier.response_url,
         SlackMessage {
             response_type: SlackResponseType::InChannel,
             blocks: response_blocks,
@@ -381,20 +584,9 @@ pub fn post_success(callback: &str, result: &RenderResult) -> anyhow::Result<()>
     )
 }
 
-pub fn post_failure(callback: &str, error: &RenderError) -> anyhow::Result<()> {
+fn post_failure(callback: &str, error: &RenderError) -> anyhow::Result<()> {
     post_slack_message(
         callback,
-        SlackMessage {
-            response_type: SlackResponseType::Ephemeral,
-            blocks: vec![SlackBlock {
-                type_: "context".to_string(),
-                elements: vec![SlackBlockElement {
-                    type_: "mrkdwn".to_string(),
-                    text: Some(format!("Error downloading: {}", error)),
-                    ..Default::default()
-                }],
-                ..Default::default()
-            }],
-        },
+        context_message(format!("Error downloading: {}", error)),
     )
 }
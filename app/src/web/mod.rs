@@ -0,0 +1,442 @@
+// Server-rendered HTML pages for the capture archive. The captures include
+// private channels and pages behind logins, so the pages require one of the
+// UDRB_ARCHIVE_USERS logins (HTTP basic auth) and are disabled without them.
+
+use crate::archive::{Archive, Capture, CaptureFilter};
+use crate::chrome::reading_time_minutes;
+use crate::config::Config;
+
+use std::sync::Arc;
+
+use base64::Engine;
+use log::warn;
+use rocket::http::{Header, Status};
+use rocket::request::{self, FromRequest, Outcome, Request};
+use rocket::response::content::RawHtml;
+use rocket::State;
+
+// Logged in archive user.
+pub struct ArchiveUser;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ArchiveUser {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let config = match req.guard::<&State<Config>>().await {
+            Outcome::Success(config) => config,
+            _ => return Outcome::Error((Status::InternalServerError, ())),
+        };
+        if !config.archive.is_enabled() {
+            return Outcome::Error((Status::NotFound, ()));
+        }
+
+        let credentials = req
+            .headers()
+            .get_one("Authorization")
+            .and_then(|h| h.strip_prefix("Basic "))
+            .and_then(|c| {
+                base64::engine::general_purpose::STANDARD
+                    .decode(c.trim())
+                    .ok()
+            })
+            .and_then(|c| String::from_utf8(c).ok());
+        let (name, password) = match credentials.as_deref().and_then(|c| c.split_once(':')) {
+            Some(credentials) => credentials,
+            None => return Outcome::Error((Status::Unauthorized, ())),
+        };
+        // Compare against all of them to not leak which one matched via timing.
+        let valid = config.archive.users.iter().fold(false, |valid, user| {
+            let name_matches =
+                constant_time_eq::constant_time_eq(user.name.as_bytes(), name.as_bytes());
+            let password_matches =
+                constant_time_eq::constant_time_eq(user.password.as_bytes(), password.as_bytes());
+            valid | (name_matches & password_matches)
+        });
+        if valid {
+            Outcome::Success(ArchiveUser)
+        } else {
+            warn!("Rejecting invalid archive login for {:?}", name);
+            Outcome::Error((Status::Unauthorized, ()))
+        }
+    }
+}
+
+// Makes browsers ask for the login.
+#[derive(rocket::Responder)]
+#[response(status = 401)]
+struct Unauthorized(&'static str, Header<'static>);
+
+#[rocket::catch(401)]
+fn unauthorized() -> Unauthorized {
+    Unauthorized(
+        "Login required",
+        Header::new(
+            "WWW-Authenticate",
+            r#"Basic realm="UDRB archive", charset="UTF-8""#,
+        ),
+    )
+}
+
+// Search results shown on the web page.
+const SEARCH_RESULT_COUNT: usize = 50;
+
+// Captures per page in the listing.
+const PAGE_SIZE: usize = 25;
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+fn page(title: &str, query: &str, body: &str) -> RawHtml<String> {
+    RawHtml(format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<meta name="referrer" content="no-referrer">
+<title>{title} - UDRB</title>
+<style>
+body {{ max-width: 60em; margin: 1em auto; padding: 0 1em; font: 16px/1.5 sans-serif; color: #222; }}
+nav {{ display: flex; gap: 1em; align-items: center; border-bottom: 1px solid #ddd; padding-bottom: 0.5em; }}
+nav form {{ margin-left: auto; }}
+.capture {{ margin: 1.5em 0; display: flex; gap: 1em; }}
+.capture h3 {{ margin: 0; }}
+.thumb {{ width: 160px; height: 100px; object-fit: cover; object-position: top; flex-shrink: 0; border: 1px solid #ddd; }}
+.filters input {{ width: 9em; }}
+.pages {{ display: flex; gap: 1em; }}
+table.info th {{ text-align: left; padding-right: 1em; vertical-align: top; }}
+.viewer {{ width: 100%; height: 80vh; border: 1px solid #ddd; overflow: auto; }}
+.viewer img {{ display: block; width: 100%; }}
+.meta {{ color: #666; font-size: 0.9em; }}
+.snippet {{ color: #444; }}
+</style>
+</head>
+<body>
+<nav>
+<strong><a href="/archive">UDRB</a></strong>
+<a href="/archive">Captures</a>
+<form action="/archive/search"><input name="q" value="{query}" placeholder="Search captures"> <button>Search</button></form>
+</nav>
+{body}
+</body>
+</html>
+"#,
+        title = escape(title),
+        query = escape(query),
+    ))
+}
+
+fn capture_links(capture: &Capture) -> String {
+    [
+        (&capture.pdf_url, "PDF"),
+        (&capture.png_url, "Screenshot"),
+        (&capture.reader_url, "Reader"),
+        (&capture.markdown_url, "Markdown"),
+        (&capture.mhtml_url, "Archive"),
+    ]
+    .into_iter()
+    .filter_map(|(url, name)| {
+        url.as_ref()
+            .map(|url| format!(r#"<a href="{}">{}</a>"#, escape(url), name))
+    })
+    .collect::<Vec<_>>()
+    .join(" · ")
+}
+
+fn capture_meta(capture: &Capture) -> String {
+    let mut meta = vec![capture.created.format("%Y-%m-%d %H:%M UTC").to_string()];
+    if let Some(host) = capture.orig_url.host_str() {
+        meta.push(escape(host));
+    }
+    if let Some(ref user) = capture.user {
+        meta.push(format!("shared by {}", escape(user)));
+    }
+    if let Some(ref channel) = capture.channel {
+        meta.push(format!("in #{}", escape(channel)));
+    }
+    meta.join(", ")
+}
+
+// Cover image from the page metadata, if any.
+fn thumbnail(capture: &Capture) -> String {
+    // Older captures have no thumbnail, use the page's cover image instead.
+    let image = capture
+        .thumbnail_url
+        .as_ref()
+        .or_else(|| capture.page_info.as_ref().and_then(|i| i.image.as_ref()));
+    match image {
+        Some(image) => format!(
+            r#"<img class="thumb" src="{}" alt="" loading="lazy">"#,
+            escape(image)
+        ),
+        None => String::new(),
+    }
+}
+
+#[derive(Debug, Default, rocket::FromForm)]
+struct ListQuery {
+    page: Option<usize>,
+    domain: Option<String>,
+    channel: Option<String>,
+    user: Option<String>,
+    // Dates as YYYY-MM-DD, invalid values are ignored.
+    from: Option<String>,
+    to: Option<String>,
+}
+
+impl ListQuery {
+    fn filter(&self) -> CaptureFilter {
+        let non_empty = |value: &Option<String>| {
+            value
+                .as_deref()
+                .map(str::trim)
+                .filter(|v| !v.is_empty())
+                .map(str::to_string)
+        };
+        let date = |value: &Option<String>| {
+            non_empty(value).and_then(|v| chrono::NaiveDate::parse_from_str(&v, "%Y-%m-%d").ok())
+        };
+        CaptureFilter {
+            domain: non_empty(&self.domain),
+            channel: non_empty(&self.channel),
+            user: non_empty(&self.user),
+            from: date(&self.from),
+            to: date(&self.to),
+        }
+    }
+
+    // Query string for the given page, keeping the filters.
+    fn page_url(&self, page: usize) -> String {
+        let mut query = url::form_urlencoded::Serializer::new(String::new());
+        for (name, value) in [
+            ("domain", &self.domain),
+            ("channel", &self.channel),
+            ("user", &self.user),
+            ("from", &self.from),
+            ("to", &self.to),
+        ] {
+            if let Some(value) = value.as_deref().filter(|v| !v.is_empty()) {
+                query.append_pair(name, value);
+            }
+        }
+        query.append_pair("page", &page.to_string());
+        format!("/archive?{}", query.finish())
+    }
+}
+
+#[rocket::get("/?<query..>")]
+fn list(_user: ArchiveUser, query: ListQuery, archive: &State<Arc<Archive>>) -> RawHtml<String> {
+    let page_number = query.page.unwrap_or(1).max(1);
+    let (captures, total) = archive.list(&query.filter(), (page_number - 1) * PAGE_SIZE, PAGE_SIZE);
+    let page_count = total.div_ceil(PAGE_SIZE).max(1);
+
+    let field = |name: &str, label: &str, value: &Option<String>, ty: &str| {
+        format!(
+            r#"<label>{label} <input type="{ty}" name="{name}" value="{value}"></label>"#,
+            value = escape(value.as_deref().unwrap_or_default()),
+        )
+    };
+    let mut body = format!(
+        r#"<form class="filters">
+{} {} {} {} {}
+<button>Filter</button> <a href="/archive">Reset</a>
+</form>
+<p>{} captures.</p>
+"#,
+        field("domain", "Domain", &query.domain, "text"),
+        field("channel", "Channel", &query.channel, "text"),
+        field("user", "User", &query.user, "text"),
+        field("from", "From", &query.from, "date"),
+        field("to", "To", &query.to, "date"),
+        total,
+    );
+    for capture in captures {
+        body.push_str(&format!(
+            r#"<div class="capture">
+{thumbnail}
+<div>
+<h3><a href="/archive/{id}">{title}</a></h3>
+<div class="meta">{meta}</div>
+<div>{links}</div>
+</div>
+</div>
+"#,
+            thumbnail = thumbnail(&capture),
+            id = escape(&capture.id),
+            title = escape(&capture.title),
+            meta = capture_meta(&capture),
+            links = capture_links(&capture),
+        ));
+    }
+
+    body.push_str(r#"<div class="pages">"#);
+    if page_number > 1 {
+        body.push_str(&format!(
+            r#"<a href="{}">&larr; Newer</a>"#,
+            escape(&query.page_url(page_number - 1))
+        ));
+    }
+    body.push_str(&format!(
+        "<span>Page {} of {}</span>",
+        page_number, page_count
+    ));
+    if page_number < page_count {
+        body.push_str(&format!(
+            r#"<a href="{}">Older &rarr;</a>"#,
+            escape(&query.page_url(page_number + 1))
+        ));
+    }
+    body.push_str("</div>");
+
+    page("Captures", "", &body)
+}
+
+#[rocket::get("/<id>")]
+fn detail(_user: ArchiveUser, id: &str, archive: &State<Arc<Archive>>) -> Option<RawHtml<String>> {
+    let capture = archive.get(id)?;
+
+    let mut rows = vec![
+        (
+            "Original",
+            format!(
+                r#"<a href="{url}">{url}</a>"#,
+                url = escape(capture.orig_url.as_str())
+            ),
+        ),
+        (
+            "Captured",
+            capture.created.format("%Y-%m-%d %H:%M:%S UTC").to_string(),
+        ),
+    ];
+    let mut optional = |name, value: Option<String>| {
+        if let Some(value) = value {
+            rows.push((name, value));
+        }
+    };
+    let info = capture.page_info.as_ref();
+    optional("Author", info.and_then(|i| i.author.as_deref()).map(escape));
+    optional(
+        "Description",
+        info.and_then(|i| i.description.as_deref()).map(escape),
+    );
+    optional(
+        "Length",
+        capture
+            .word_count
+            .map(|count| format!("{} words, {} min read", count, reading_time_minutes(count))),
+    );
+    optional("Shared by", capture.user.as_deref().map(escape));
+    optional(
+        "Channel",
+        capture
+            .channel
+            .as_deref()
+            .map(|c| format!("#{}", escape(c))),
+    );
+    optional("Team", capture.team.as_deref().map(escape));
+    optional("Files", Some(capture_links(&capture)));
+
+    let mut body = format!(
+        r#"<div class="capture">{thumbnail}<h2>{title}</h2></div>
+<table class="info">
+"#,
+        thumbnail = thumbnail(&capture),
+        title = escape(&capture.title),
+    );
+    for (name, value) in rows {
+        body.push_str(&format!("<tr><th>{}</th><td>{}</td></tr>\n", name, value));
+    }
+    body.push_str("</table>\n");
+
+    if let Some(ref png_url) = capture.png_url {
+        // Long pages are split into tiles, show them one after another.
+        let tiles = if capture.screenshot_tiles.is_empty() {
+            std::slice::from_ref(png_url)
+        } else {
+            &capture.screenshot_tiles[..]
+        };
+        let images: String = tiles
+            .iter()
+            .map(|url| {
+                format!(
+                    r#"<img src="{}" alt="Screenshot" loading="lazy">"#,
+                    escape(url)
+                )
+            })
+            .collect();
+        body.push_str(&format!(
+            r#"<details open><summary>Screenshot</summary><div class="viewer">{}</div></details>
+"#,
+            images
+        ));
+    }
+    if let Some(ref pdf_url) = capture.pdf_url {
+        body.push_str(&format!(
+            r#"<details{}><summary>PDF</summary><iframe class="viewer" src="{}"></iframe></details>
+"#,
+            if capture.png_url.is_none() {
+                " open"
+            } else {
+                ""
+            },
+            escape(pdf_url)
+        ));
+    }
+
+    Some(page(&capture.title, "", &body))
+}
+
+#[rocket::get("/search?<q>")]
+fn search(_user: ArchiveUser, q: Option<&str>, archive: &State<Arc<Archive>>) -> RawHtml<String> {
+    let query = q.unwrap_or_default().trim();
+    if query.is_empty() {
+        return page(
+            "Search",
+            "",
+            "<p>Search the text of all captured pages.</p>",
+        );
+    }
+
+    let hits = archive.search(query, SEARCH_RESULT_COUNT);
+    let mut body = format!(
+        "<p>{} captures matching <em>{}</em>.</p>",
+        hits.len(),
+        escape(query)
+    );
+    for hit in hits {
+        let capture = &hit.capture;
+        body.push_str(&format!(
+            r#"<div class="capture">
+{thumbnail}
+<div>
+<h3><a href="/archive/{id}">{title}</a></h3>
+<div class="meta">{meta}</div>
+<div class="snippet">{snippet}</div>
+<div>{links}</div>
+</div>
+</div>
+"#,
+            thumbnail = thumbnail(capture),
+            id = escape(&capture.id),
+            title = escape(&capture.title),
+            meta = capture_meta(capture),
+            snippet = escape(&hit.snippet),
+            links = capture_links(capture),
+        ));
+    }
+    page(&format!("Search: {}", query), query, &body)
+}
+
+pub fn routes() -> Vec<rocket::Route> {
+    rocket::routes![list, detail, search]
+}
+
+pub fn catchers() -> Vec<rocket::Catcher> {
+    rocket::catchers![unauthorized]
+}